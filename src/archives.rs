@@ -0,0 +1,218 @@
+use crate::configrc::rc_get;
+use crate::consts::{ARCHIVE_CACHE_SIZE_DEFAULT, DVM_CONFIGRC_KEY_ARCHIVE_CACHE_SIZE};
+use crate::utils::{copy_atomic, dvm_archives, sha256_reader, write_atomic};
+use anyhow::Result;
+use semver::Version;
+use std::fs;
+use std::io::{self, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::SystemTime;
+
+/// held while the cache is read or changed, several versions are installed at once by threads
+static CACHE_LOCK: Mutex<()> = Mutex::new(());
+
+fn lock() -> MutexGuard<'static, ()> {
+  // nothing guarded can be left inconsistent by a panic, the files are written atomically
+  CACHE_LOCK.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// An archive in the cache, laid out like a registry: `release/v{version}/{name}`
+/// with its digest next to it in `{name}.sha256sum`.
+#[derive(Debug, Clone)]
+pub struct CachedArchive {
+  pub version: Version,
+  pub name: String,
+  pub path: PathBuf,
+  pub size: u64,
+  pub last_used: SystemTime,
+}
+
+/// the cache size limit in bytes, `archive_cache_size` in the `.dvmrc` in MiB
+pub fn max_size() -> u64 {
+  let mebibytes = rc_get(DVM_CONFIGRC_KEY_ARCHIVE_CACHE_SIZE)
+    .ok()
+    .and_then(|it| it.trim().parse::<u64>().ok())
+    .unwrap_or(ARCHIVE_CACHE_SIZE_DEFAULT);
+  mebibytes.saturating_mul(1024 * 1024)
+}
+
+/// the relative path of an archive, the same in the cache and in a registry
+pub fn relative_path(version: &Version, name: &str) -> PathBuf {
+  Path::new("release").join(format!("v{}", version)).join(name)
+}
+
+/// the path of the `.sha256sum` file next to an archive
+pub fn checksum_path(path: &Path) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_os_string();
+  name.push(".sha256sum");
+  path.with_file_name(name)
+}
+
+pub fn contains(version: &Version, name: &str) -> bool {
+  dvm_archives().join(relative_path(version, name)).is_file()
+}
+
+/// open a cached archive and check it against its digest,
+/// a corrupted archive is removed from the cache.
+pub fn get(version: &Version, name: &str) -> Result<Option<(fs::File, String)>> {
+  let _lock = lock();
+  let path = dvm_archives().join(relative_path(version, name));
+  if !path.is_file() {
+    return Ok(None);
+  }
+
+  let mut file = fs::File::open(&path)?;
+  let digest = sha256_reader(&mut file)?;
+  let expected = fs::read_to_string(checksum_path(&path)).unwrap_or_default();
+  if expected.split_whitespace().next() != Some(digest.as_str()) {
+    eprintln!("Removing the corrupted cached archive {}", path.display());
+    drop(file);
+    remove(&path)?;
+    return Ok(None);
+  }
+  file.rewind()?;
+
+  // the modification time tracks when the archive was last used
+  fs::File::options()
+    .write(true)
+    .open(&path)?
+    .set_modified(SystemTime::now())?;
+  Ok(Some((file, digest)))
+}
+
+/// add an archive verified against `digest` to the cache and shrink the cache to its size limit
+pub fn put(version: &Version, name: &str, archive: &Path, digest: &str) -> Result<()> {
+  let max_size = max_size();
+  if max_size == 0 {
+    return Ok(());
+  }
+
+  let _lock = lock();
+  let path = dvm_archives().join(relative_path(version, name));
+  fs::create_dir_all(path.parent().unwrap())?;
+  copy_atomic(&path, fs::File::open(archive)?)?;
+  write_atomic(&checksum_path(&path), format!("{}  {}\n", digest, name))?;
+  prune_locked(max_size)?;
+  Ok(())
+}
+
+/// every cached archive, the most recently used first
+pub fn list() -> Vec<CachedArchive> {
+  list_in(&dvm_archives())
+}
+
+/// every archive in a directory in the registry layout, the most recently used first
+pub fn list_in(root: &Path) -> Vec<CachedArchive> {
+  let mut archives = Vec::new();
+  let Ok(versions) = fs::read_dir(root.join("release")) else {
+    return archives;
+  };
+
+  for version_dir in versions.flatten() {
+    let dir_name = version_dir.file_name().to_string_lossy().to_string();
+    let Ok(version) = Version::parse(dir_name.trim_start_matches('v')) else {
+      continue;
+    };
+    let Ok(entries) = fs::read_dir(version_dir.path()) else {
+      continue;
+    };
+    for entry in entries.flatten() {
+      let name = entry.file_name().to_string_lossy().to_string();
+      if name.ends_with(".sha256sum") {
+        continue;
+      }
+      let Ok(metadata) = entry.metadata() else {
+        continue;
+      };
+      if metadata.is_file() {
+        archives.push(CachedArchive {
+          version: version.clone(),
+          name,
+          path: entry.path(),
+          size: metadata.len(),
+          last_used: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+        });
+      }
+    }
+  }
+
+  archives.sort_by_key(|it| std::cmp::Reverse(it.last_used));
+  archives
+}
+
+/// remove the least recently used archives until the cache fits into `max_size` bytes,
+/// returns the removed archives
+pub fn prune(max_size: u64) -> Result<Vec<CachedArchive>> {
+  let _lock = lock();
+  prune_locked(max_size)
+}
+
+fn prune_locked(max_size: u64) -> Result<Vec<CachedArchive>> {
+  let mut archives = list();
+  let mut total: u64 = archives.iter().map(|it| it.size).sum();
+  let mut removed = Vec::new();
+  while total > max_size {
+    let Some(archive) = archives.pop() else {
+      break;
+    };
+    remove(&archive.path)?;
+    total -= archive.size;
+    removed.push(archive);
+  }
+  Ok(removed)
+}
+
+/// copy the cached archives of the versions, or all of them, into `dest` in the registry layout
+pub fn export(dest: &Path, versions: &[Version]) -> Result<Vec<CachedArchive>> {
+  let archives: Vec<CachedArchive> = list()
+    .into_iter()
+    .filter(|it| versions.is_empty() || versions.contains(&it.version))
+    .collect();
+
+  for archive in &archives {
+    let target = dest.join(relative_path(&archive.version, &archive.name));
+    fs::create_dir_all(target.parent().unwrap())?;
+    fs::copy(&archive.path, &target)?;
+    let checksum = checksum_path(&archive.path);
+    if checksum.exists() {
+      fs::copy(checksum, checksum_path(&target))?;
+    }
+  }
+  Ok(archives)
+}
+
+fn remove(path: &Path) -> Result<()> {
+  remove_file_if_exists(path)?;
+  remove_file_if_exists(&checksum_path(path))?;
+  if let Some(dir) = path.parent() {
+    // only removed once the version has no archives left
+    let _ = fs::remove_dir(dir);
+  }
+  Ok(())
+}
+
+/// an archive removed by another dvm process in the meantime is gone as well
+fn remove_file_if_exists(path: &Path) -> io::Result<()> {
+  match fs::remove_file(path) {
+    Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+    result => result,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn archives_use_the_registry_layout() {
+    let version = Version::parse("1.40.0").unwrap();
+    let path = relative_path(&version, "deno-x86_64-unknown-linux-gnu.zip");
+
+    assert_eq!(path, Path::new("release/v1.40.0/deno-x86_64-unknown-linux-gnu.zip"));
+    assert_eq!(
+      checksum_path(&path),
+      Path::new("release/v1.40.0/deno-x86_64-unknown-linux-gnu.zip.sha256sum")
+    );
+  }
+}
@@ -1,19 +1,26 @@
 use std::env;
+use std::path::PathBuf;
 
 use clap::builder::PossibleValue;
-use clap::{Parser, ValueEnum};
+use clap::{ArgGroup, Parser, ValueEnum};
 use clap_complete::Shell;
 use clap_derive::{Parser, Subcommand};
 
 use crate::commands;
 use crate::consts::{
-  AFTER_HELP, COMPLETIONS_HELP, REGISTRY_CN, REGISTRY_LIST_CN, REGISTRY_LIST_OFFICIAL, REGISTRY_NAME_CN,
-  REGISTRY_NAME_OFFICIAL, REGISTRY_OFFICIAL,
+  AFTER_HELP, COMPLETIONS_HELP, INSTALL_JOBS_DEFAULT, REGISTRY_NAME_AUTO, REGISTRY_NAME_CN, REGISTRY_NAME_OFFICIAL,
+  REGISTRY_SERVE_BIND_DEFAULT, REGISTRY_SERVE_PORT_DEFAULT,
 };
+use crate::http;
 use crate::meta::DvmMeta;
 
 pub fn cli_parse(meta: &mut DvmMeta) -> Result<Cli, ()> {
-  let args: Vec<String> = env::args().collect();
+  let mut args: Vec<String> = env::args().collect();
+  // `dvm --offline exec` is handled before clap sees the flag
+  if args.len() > 1 && args[1] == "--offline" {
+    http::set_offline();
+    args.remove(1);
+  }
   if args.len() > 1 && args[1] == "exec" {
     if args.len() > 2 {
       let version: Option<String>;
@@ -38,9 +45,9 @@ pub fn cli_parse(meta: &mut DvmMeta) -> Result<Cli, ()> {
         version = None;
         exec_args = args[2..].to_vec();
       }
-      commands::exec::exec(meta, version, exec_args).unwrap();
+      exit_on_error(commands::exec::exec(meta, version, exec_args));
     } else {
-      commands::exec::exec(meta, None, vec![]).unwrap();
+      exit_on_error(commands::exec::exec(meta, None, vec![]));
     }
     return Err(());
   }
@@ -48,11 +55,25 @@ pub fn cli_parse(meta: &mut DvmMeta) -> Result<Cli, ()> {
   Ok(Cli::parse())
 }
 
+fn exit_on_error(result: anyhow::Result<()>) {
+  if let Err(err) = result {
+    eprintln!("\x1b[31merror:\x1b[39m: {}", err);
+    std::process::exit(1);
+  }
+}
+
 #[derive(Parser)]
 #[clap(version, about)]
 #[clap(after_help = AFTER_HELP)]
 #[clap(propagate_version = true)]
 pub struct Cli {
+  #[clap(
+    long,
+    global = true,
+    help = "Never touch the network, resolve versions against installed versions and cached lists only"
+  )]
+  pub offline: bool,
+
   #[clap(subcommand)]
   pub command: Commands,
 }
@@ -75,8 +96,28 @@ pub enum Commands {
   Install {
     #[clap(long, help = "Only install to local, but not use")]
     no_use: bool,
-    #[clap(help = "The version to install")]
-    version: Option<String>,
+    #[clap(
+      value_name = "VERSION",
+      help = "The versions to install, a range installs every matching release"
+    )]
+    versions: Vec<String>,
+    #[clap(
+      long,
+      short,
+      default_value_t = INSTALL_JOBS_DEFAULT,
+      value_parser = clap::value_parser!(u32).range(1..),
+      help = "How many versions to download at once when installing several"
+    )]
+    jobs: u32,
+    #[clap(
+      long,
+      value_name = "ARCHIVE",
+      conflicts_with = "from_binary",
+      help = "Install from a local release archive instead of the registry"
+    )]
+    from_file: Option<PathBuf>,
+    #[clap(long, value_name = "DENO", help = "Install a copy of an existing deno executable")]
+    from_binary: Option<PathBuf>,
   },
 
   #[clap(about = "List all installed versions")]
@@ -91,10 +132,21 @@ pub enum Commands {
   #[clap(visible_aliases = & ["un", "unlink", "rm", "remove"])]
   #[clap(disable_version_flag = true)]
   Uninstall {
-    #[clap(help = "The version to uninstall")]
+    #[clap(help = "The version to uninstall, or the name of a linked toolchain to unregister")]
     version: Option<String>,
   },
 
+  #[clap(about = "Use a deno executable outside of dvm, e.g. a local build, by a name")]
+  Link {
+    #[clap(
+      requires = "path",
+      help = "The name to use it by, list linked toolchains if not present"
+    )]
+    name: Option<String>,
+    #[clap(help = "The path of the deno executable")]
+    path: Option<PathBuf>,
+  },
+
   #[clap(about = "Use a given version or a semver range or a alias to the range.")]
   #[clap(disable_version_flag = true)]
   Use {
@@ -139,6 +191,17 @@ pub enum Commands {
     version: Option<String>,
   },
 
+  #[clap(about = "Show the version a version argument, or the .dvmrc file, resolves to")]
+  #[clap(disable_version_flag = true)]
+  Resolve {
+    #[clap(help = "The version, range, alias or toolchain to resolve, the .dvmrc file is used without one")]
+    version: Option<String>,
+    #[clap(long, help = "Show how the version was chosen")]
+    explain: bool,
+    #[clap(long, help = "Print the resolution as JSON")]
+    json: bool,
+  },
+
   #[clap(about = "Clean dvm cache")]
   Clean,
 
@@ -150,6 +213,38 @@ pub enum Commands {
 
   #[clap(about = "Update remove version list local cache to the latest")]
   Update,
+
+  #[clap(about = "Manage the cache of downloaded archives")]
+  Cache {
+    #[clap(subcommand)]
+    command: CacheCommands,
+  },
+
+  #[clap(about = "Build a registry directory for machines without access to the registry")]
+  Mirror {
+    #[clap(subcommand)]
+    command: MirrorCommands,
+  },
+}
+
+impl Commands {
+  /// whether the command changes the state of `DVM_DIR` and has to hold the lock
+  pub fn is_mutating(&self) -> bool {
+    !matches!(
+      self,
+      Commands::Completions { .. }
+        | Commands::Info
+        | Commands::List
+        | Commands::Exec { .. }
+        | Commands::Resolve { .. }
+        | Commands::Cache {
+          command: CacheCommands::List
+        }
+        | Commands::Registry {
+          command: RegistryCommands::Serve { .. } | RegistryCommands::Ping
+        }
+    )
+  }
 }
 
 #[derive(Subcommand)]
@@ -172,6 +267,45 @@ pub enum AliasCommands {
   List,
 }
 
+#[derive(Subcommand)]
+pub enum CacheCommands {
+  #[clap(about = "List cached archives, the most recently used first")]
+  List,
+
+  #[clap(about = "Remove cached archives, the least recently used first")]
+  Prune {
+    #[clap(long, help = "Only remove archives until the cache is at most this many MiB")]
+    max_size: Option<u64>,
+  },
+
+  #[clap(about = "Copy cached archives into a directory in the registry layout, to seed another machine")]
+  Export {
+    #[clap(help = "The directory to copy the archives into")]
+    dest: PathBuf,
+    #[clap(help = "The versions to export, all cached versions if not present")]
+    versions: Vec<String>,
+  },
+}
+
+#[derive(Subcommand)]
+pub enum MirrorCommands {
+  #[clap(about = "Download the releases in a range into a directory, skipping those already there")]
+  Sync {
+    #[clap(long, help = "The semver range of the releases to mirror, `*` for every release")]
+    range: String,
+    #[clap(
+      long,
+      value_delimiter = ',',
+      help = "The targets to mirror, e.g. linux-x64,darwin-arm64, the current one if not present"
+    )]
+    targets: Vec<String>,
+    #[clap(long, help = "The directory to build the registry in")]
+    dest: PathBuf,
+    #[clap(long, help = "Also mirror the latest canary")]
+    canary: bool,
+  },
+}
+
 #[derive(Subcommand)]
 pub enum RegistryCommands {
   #[clap(about = "List predefined registries")]
@@ -200,7 +334,10 @@ pub enum RegistryCommands {
     write_local: bool,
   },
 
-  #[clap(about = "Set registry to one of predefined registries")]
+  #[clap(about = "Check every known registry for reachability, latency and a consistent version list")]
+  Ping,
+
+  #[clap(about = "Set registry to one of predefined registries, or `auto` for the fastest healthy one")]
   Set {
     predefined: RegistryPredefined,
 
@@ -212,6 +349,109 @@ pub enum RegistryCommands {
     write_local: bool,
   },
 
+  #[clap(about = "Save a registry under a name to switch to it with `dvm registry use`")]
+  #[clap(disable_version_flag = true)]
+  Add {
+    #[clap(help = "The name of the registry")]
+    name: String,
+    #[clap(long, help = "The binary registry url, or a comma-separated list of mirrors")]
+    binary: String,
+    #[clap(
+      long,
+      help = "The version list url, `versions.json` in the binary registry if not present"
+    )]
+    version: Option<String>,
+    #[clap(
+      long,
+      help = "Where the latest LTS version is looked up, a file with the version or the Deno releases page"
+    )]
+    lts: Option<String>,
+    #[clap(
+      long,
+      help = "Where a release is in the binary registry, with {version}, {target} and {archive} placeholders"
+    )]
+    release_template: Option<String>,
+    #[clap(
+      long,
+      help = "Where a canary is in the binary registry, {version} is the commit hash"
+    )]
+    canary_template: Option<String>,
+    #[clap(long, help = "Read a bearer token from this environment variable on every request")]
+    token_env: Option<String>,
+    #[clap(long, help = "A bearer token, stored in the credentials file")]
+    token: Option<String>,
+    #[clap(long, help = "Username for basic auth")]
+    username: Option<String>,
+    #[clap(long, requires = "username", help = "Password for basic auth")]
+    password: Option<String>,
+  },
+
+  #[clap(about = "Remove a saved registry, its credentials are kept until `dvm registry logout`")]
+  Remove {
+    #[clap(help = "The name given to `dvm registry add`")]
+    name: String,
+  },
+
+  #[clap(about = "Switch to a predefined or a saved registry")]
+  Use {
+    #[clap(help = "`official`, `cn`, `auto` or the name given to `dvm registry add`")]
+    name: String,
+    #[clap(
+      long = "write-local",
+      short = 'L',
+      help = "Write to current directory .dvmrc file instead of global(user-wide) config"
+    )]
+    write_local: bool,
+  },
+
+  #[clap(about = "Store credentials for registry urls starting with the given url")]
+  #[clap(group(ArgGroup::new("auth").required(true).args(["token_env", "token", "username"])))]
+  Login {
+    #[clap(help = "The registry url, credentials apply to every url it is a prefix of")]
+    url: String,
+    #[clap(long, help = "Read a bearer token from this environment variable on every request")]
+    token_env: Option<String>,
+    #[clap(long, help = "A bearer token, stored in the credentials file")]
+    token: Option<String>,
+    #[clap(long, help = "Username for basic auth")]
+    username: Option<String>,
+    #[clap(long, requires = "username", help = "Password for basic auth")]
+    password: Option<String>,
+  },
+
+  #[clap(about = "Remove the stored credentials of a registry url")]
+  Logout {
+    #[clap(help = "The registry url given to `dvm registry login`")]
+    url: String,
+  },
+
+  #[clap(about = "Serve a registry to other dvm clients, fetching what is not cached yet from an upstream registry")]
+  Serve {
+    #[clap(long, default_value_t = REGISTRY_SERVE_PORT_DEFAULT, help = "The port to listen on")]
+    port: u16,
+    #[clap(
+      long,
+      default_value = REGISTRY_SERVE_BIND_DEFAULT,
+      help = "The address to listen on, `0.0.0.0` to serve other machines"
+    )]
+    bind: String,
+    #[clap(
+      long,
+      help = "`official`, `cn` or the url of a binary registry, the current registry if not present"
+    )]
+    upstream: Option<String>,
+    #[clap(
+      long,
+      help = "The version list of a custom upstream, `versions.json` in the upstream if not present"
+    )]
+    upstream_versions: Option<String>,
+    #[clap(
+      long,
+      help = "The directory to serve and to cache fetched files in, e.g. one built by `dvm mirror sync`"
+    )]
+    dir: Option<PathBuf>,
+  },
+
   #[clap(about = "Binary registry operations")]
   Binary {
     #[clap(subcommand)]
@@ -261,11 +501,16 @@ pub enum VersionRegistryCommands {
 pub enum RegistryPredefined {
   Official,
   CN,
+  Auto,
 }
 
 impl ValueEnum for RegistryPredefined {
   fn value_variants<'a>() -> &'a [Self] {
-    &[RegistryPredefined::Official, RegistryPredefined::CN]
+    &[
+      RegistryPredefined::Official,
+      RegistryPredefined::CN,
+      RegistryPredefined::Auto,
+    ]
   }
 
   fn from_str(input: &str, ignore_case: bool) -> Result<Self, String> {
@@ -273,6 +518,8 @@ impl ValueEnum for RegistryPredefined {
       Ok(RegistryPredefined::Official)
     } else if (ignore_case && REGISTRY_NAME_CN == input.to_ascii_lowercase()) || REGISTRY_NAME_CN == input {
       Ok(RegistryPredefined::CN)
+    } else if (ignore_case && REGISTRY_NAME_AUTO == input.to_ascii_lowercase()) || REGISTRY_NAME_AUTO == input {
+      Ok(RegistryPredefined::Auto)
     } else {
       Err(format!("{} is not a valid registry", input))
     }
@@ -282,31 +529,20 @@ impl ValueEnum for RegistryPredefined {
     Some(PossibleValue::new(match self {
       RegistryPredefined::Official => REGISTRY_NAME_OFFICIAL,
       RegistryPredefined::CN => REGISTRY_NAME_CN,
+      RegistryPredefined::Auto => REGISTRY_NAME_AUTO,
     }))
   }
 }
 
-impl RegistryPredefined {
-  pub fn get_version_url(&self) -> String {
-    match self {
-      RegistryPredefined::Official => REGISTRY_LIST_OFFICIAL,
-      RegistryPredefined::CN => REGISTRY_LIST_CN,
-    }
-    .to_string()
-  }
-
-  pub fn get_binary_url(&self) -> String {
-    match self {
-      RegistryPredefined::Official => REGISTRY_OFFICIAL,
-      RegistryPredefined::CN => REGISTRY_CN,
-    }
-    .to_string()
-  }
-}
-
 #[cfg(test)]
 mod tests {
   use super::*;
+  use clap::CommandFactory;
+
+  #[test]
+  fn cli_is_consistent() {
+    Cli::command().debug_assert();
+  }
 
   #[test]
   fn parses_registry_predefined_shortcuts() {
@@ -1,10 +1,13 @@
 use crate::cli::AliasCommands;
-use crate::version::{find_max_matching_version, local_versions, remote_versions, version_req_parse};
+use crate::http;
+use crate::resolve::{parse_alias_target, Spec};
+use crate::version::{find_max_matching_version, is_versions_cache_exists, local_versions, remote_versions};
 use crate::{DvmMeta, DEFAULT_ALIAS};
 
 use anyhow::Result;
 use colored::{ColoredString, Colorize};
 use phf::phf_map;
+use semver::VersionReq;
 
 const ALIAS_COLORS: phf::Map<&str, (u8, u8, u8)> = phf_map! {
     "lighter" => (0xD1, 0xFA, 0xFF),        // unused
@@ -25,7 +28,16 @@ fn apply_alias_color(a: &str, c: &str) -> ColoredString {
 pub fn exec(meta: &mut DvmMeta, command: AliasCommands) -> Result<()> {
   match command {
     AliasCommands::Set { name, content } => {
-      version_req_parse(content.as_str());
+      if meta.get_toolchain(&name).is_some() {
+        anyhow::bail!(
+          "`{}` is already a linked toolchain, choose another name for the alias",
+          name
+        );
+      }
+      // an alias points to a version, a partial version, a range, a keyword or a linked toolchain
+      if parse_alias_target(meta, &content).is_none() {
+        anyhow::bail!("`{}` is not a version, a range or a linked toolchain", content);
+      }
       meta.set_alias(name, content);
       Ok(())
     }
@@ -34,14 +46,22 @@ pub fn exec(meta: &mut DvmMeta, command: AliasCommands) -> Result<()> {
       Ok(())
     }
     AliasCommands::List => {
-      let remote_versions = remote_versions().unwrap();
+      // upgrade hints need the cached remote list, offline they are skipped without it
+      let remote_versions = if http::is_offline() && !is_versions_cache_exists() {
+        Vec::new()
+      } else {
+        remote_versions()?
+      };
       let local_versions = local_versions();
+      // partial versions mean the same here as for `dvm use`, `1.40` only ever upgrades to a 1.40.x
       let get_upgrade_version = |version_str: &str| {
-        let max_remote_version =
-          find_max_matching_version(version_str, remote_versions.iter().map(AsRef::as_ref)).unwrap();
-
-        let max_local_version =
-          find_max_matching_version(version_str, local_versions.iter().map(AsRef::as_ref)).unwrap();
+        let version_req = match parse_alias_target(meta, version_str)? {
+          Spec::Range(range) => range,
+          Spec::Latest => VersionReq::STAR,
+          _ => return None,
+        };
+        let max_remote_version = find_max_matching_version(&version_req, remote_versions.iter().map(AsRef::as_ref));
+        let max_local_version = find_max_matching_version(&version_req, local_versions.iter().map(AsRef::as_ref));
         if let (Some(max_remote), Some(max_local)) = (max_remote_version, max_local_version) {
           if max_remote > max_local {
             return Some(max_remote);
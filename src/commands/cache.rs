@@ -0,0 +1,55 @@
+use crate::archives;
+use crate::cli::CacheCommands;
+use anyhow::Result;
+use colored::Colorize;
+use indicatif::HumanBytes;
+use semver::Version;
+
+pub fn exec(command: CacheCommands) -> Result<()> {
+  match command {
+    CacheCommands::List => {
+      let archives = archives::list();
+      for archive in &archives {
+        println!(
+          "  v{}\t{}\t{}",
+          archive.version,
+          archive.name.bright_black(),
+          HumanBytes(archive.size)
+        );
+      }
+      let total: u64 = archives.iter().map(|it| it.size).sum();
+      println!(
+        "{} archives, {} of {}",
+        archives.len(),
+        HumanBytes(total),
+        HumanBytes(archives::max_size())
+      );
+    }
+    CacheCommands::Prune { max_size } => {
+      let removed = archives::prune(max_size.unwrap_or(0).saturating_mul(1024 * 1024))?;
+      for archive in &removed {
+        println!("Removed v{} {}", archive.version, archive.name.bright_black());
+      }
+      let freed: u64 = removed.iter().map(|it| it.size).sum();
+      println!("Removed {} archives, {} freed", removed.len(), HumanBytes(freed));
+    }
+    CacheCommands::Export { dest, versions } => {
+      let versions = versions
+        .iter()
+        .map(|it| Version::parse(it.trim_start_matches('v')).map_err(|_| anyhow::anyhow!("Invalid semver {}", it)))
+        .collect::<Result<Vec<_>>>()?;
+      let exported = archives::export(&dest, &versions)?;
+      if exported.is_empty() {
+        anyhow::bail!("No cached archive to export");
+      }
+      for archive in &exported {
+        println!("Exported v{} {}", archive.version, archive.name.bright_black());
+      }
+      println!(
+        "Copy {} into the archive cache of another machine, or serve it as a registry",
+        dest.display()
+      );
+    }
+  }
+  Ok(())
+}
@@ -53,6 +53,9 @@ pub fn exec(meta: &mut DvmMeta) -> Result<()> {
     }
   }
 
+  // leftovers of interrupted installs
+  super::install::clean_staging()?;
+
   if dvm_root().exists() {
     super::use_version::exec(meta, None, false).unwrap();
   }
@@ -1,65 +1,69 @@
+use std::path::Path;
 use std::process::Stdio;
 
 use crate::{
-  consts::{DVM_VERSION_LATEST, DVM_VERSION_LTS},
+  archives,
+  consts::{ARCHIVE_NAME, DVM_VERSION_CANARY, DVM_VERSION_LATEST},
+  http, lock,
   meta::DvmMeta,
-  utils::{best_version, deno_version_path, is_exact_version, prompt_request},
-  version::{get_latest_lts_version, remote_versions, VersionArg},
+  resolve::{resolve_cached, system_deno, Resolved},
+  utils::{deno_canary_path, deno_version_path, prompt_request},
 };
 use anyhow::Result;
 use colored::Colorize;
-use semver::Version;
 
 use super::install;
 
 pub fn exec(meta: &mut DvmMeta, version: Option<String>, args: Vec<String>) -> Result<()> {
-  let versions = remote_versions().expect("Failed to get remote versions");
   let version = version.unwrap_or_else(|| DVM_VERSION_LATEST.to_string());
-  let v = version.clone();
+  let resolution = resolve_cached(meta, &version)?;
+  for line in resolution.explain() {
+    println!("{}", line);
+  }
 
-  let version = if is_exact_version(&version) {
-    version
-  } else if version == DVM_VERSION_LTS {
-    println!("Checking for latest LTS version");
-    let version = get_latest_lts_version()?;
-    println!("The latest LTS version is v{}", version);
-    version.to_string()
-  } else if meta.has_alias(&v) {
-    let version_req = meta.resolve_version_req(&v);
-    match version_req {
-      VersionArg::Exact(v) => v.to_string(),
-      VersionArg::Lts => {
-        println!("Checking for latest LTS version");
-        let version = get_latest_lts_version()?;
-        println!("The latest LTS version is v{}", version);
-        version.to_string()
-      }
-      VersionArg::Range(r) => {
-        let best = best_version(versions.iter().map(AsRef::as_ref), r.clone());
-        if let Some(best) = best {
-          best.to_string()
+  let parsed_version = match resolution.resolved {
+    Resolved::Version(version) => version,
+    Resolved::Toolchain { path, .. } => return run(&path, args),
+    Resolved::System => return run(&system_deno()?, args),
+    Resolved::Canary => {
+      let canary_path = deno_canary_path();
+      if !canary_path.exists() {
+        if prompt_request("deno canary is not installed. do you want to install it?") {
+          let _lock = lock::acquire()?;
+          meta.reload();
+          install::exec(meta, true, Some(DVM_VERSION_CANARY.to_string()))?;
         } else {
-          eprintln!("No version found for {} in {:?}", r, versions);
+          eprintln!("{}", "No such version found.".red());
           std::process::exit(1);
         }
       }
+      return run(&canary_path, args);
     }
-  } else {
-    eprintln!("{}", "No such alias or version found.".red());
-    std::process::exit(1);
   };
-
-  let executable_path = deno_version_path(&Version::parse(&version).unwrap());
+  let version = parsed_version.to_string();
+  let executable_path = deno_version_path(&parsed_version);
 
   if !executable_path.exists() {
+    if http::is_offline() && !archives::contains(&parsed_version, ARCHIVE_NAME) {
+      anyhow::bail!(
+        "deno v{} is not installed and cannot be downloaded in offline mode",
+        version
+      );
+    }
     if prompt_request(format!("deno v{} is not installed. do you want to install it?", version).as_str()) {
-      install::exec(meta, true, Some(version.clone())).unwrap_or_else(|_| panic!("Failed to install deno {}", version));
+      let _lock = lock::acquire()?;
+      meta.reload();
+      install::exec(meta, true, Some(version.clone()))?;
     } else {
       eprintln!("{}", "No such version found.".red());
       std::process::exit(1);
     }
   }
 
+  run(&executable_path, args)
+}
+
+fn run(executable_path: &Path, args: Vec<String>) -> Result<()> {
   let mut cmd = std::process::Command::new(executable_path)
     .args(args)
     .stderr(Stdio::inherit())
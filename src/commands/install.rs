@@ -1,49 +1,62 @@
 // Copyright 2018-2020 the Deno authors. All rights reserved. MIT license.
 // Copyright 2020-2022 justjavac. All rights reserved. MIT license.
 use super::use_version;
-use crate::configrc::rc_get_with_fix;
+use crate::archives;
+use crate::configrc::{rc_get, rc_get_with_fix};
 use crate::consts::{
-  DVM_CACHE_PATH_PREFIX, DVM_CANARY_PATH_PREFIX, DVM_CONFIGRC_KEY_REGISTRY_BINARY, DVM_CONFIGRC_KEY_REGISTRY_VERSION,
-  DVM_VERSION_CANARY, DVM_VERSION_LATEST, DVM_VERSION_LTS, REGISTRY_LIST_OFFICIAL, REGISTRY_OFFICIAL,
+  ARCHIVE_NAME, DENO_EXE, DVM_CACHE_PATH_PREFIX, DVM_CANARY_PATH_PREFIX, DVM_CONFIGRC_KEY_REGISTRY_BINARY,
+  DVM_CONFIGRC_KEY_REGISTRY_CANARY_TEMPLATE, DVM_CONFIGRC_KEY_REGISTRY_RELEASE_TEMPLATE,
+  DVM_CONFIGRC_KEY_REQUIRE_CHECKSUM, DVM_VERSION_CANARY, DVM_VERSION_LATEST, REGISTRY_CANARY_TEMPLATE_DEFAULT,
+  REGISTRY_OFFICIAL, REGISTRY_RELEASE_TEMPLATE_DEFAULT,
 };
+use crate::http::{self, HttpConfig};
 use crate::meta::DvmMeta;
-use crate::utils::{deno_canary_path, deno_version_path, dvm_root};
-use crate::version::{get_latest_canary, get_latest_lts_version, get_latest_remote_version};
+use crate::oci;
+use crate::resolve::{expand, resolve, Resolved};
+use crate::utils::{
+  deno_canary_path, deno_version_path, dvm_downloads, dvm_root, dvm_staging, sha256_reader, Reporter,
+};
+use crate::version::get_latest_canary;
 use anyhow::Result;
-use cfg_if::cfg_if;
+use colored::Colorize;
+use indicatif::{HumanBytes, MultiProgress};
 use semver::Version;
 use std::fs;
+use std::io::{self, Read, Seek, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use std::string::String;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use tempfile::{TempDir, TempPath};
+use zip::ZipArchive;
 
-cfg_if! {
-  if #[cfg(windows)] {
-    const ARCHIVE_NAME: &str = "deno-x86_64-pc-windows-msvc.zip";
-  } else if #[cfg(all(target_os = "macos", target_arch = "aarch64"))] {
-    const ARCHIVE_NAME: &str = "deno-aarch64-apple-darwin.zip";
-  } else if #[cfg(all(target_os = "macos", target_arch = "x86_64"))] {
-    const ARCHIVE_NAME: &str = "deno-x86_64-apple-darwin.zip";
-  } else if #[cfg(all(target_os = "linux", target_arch = "x86_64"))] {
-    const ARCHIVE_NAME: &str = "deno-x86_64-unknown-linux-gnu.zip";
-  } else if #[cfg(all(target_os = "linux", target_arch = "aarch64"))] {
-    const ARCHIVE_NAME: &str = "deno-aarch64-unknown-linux-gnu.zip";
-  }
-}
+const DOWNLOAD_BUFFER_SIZE: usize = 64 * 1024;
+
+pub fn exec(meta: &mut DvmMeta, no_use: bool, version: Option<String>) -> Result<()> {
+  clean_staging()?;
 
-pub fn exec(_: &DvmMeta, no_use: bool, version: Option<String>) -> Result<()> {
   let binary_registry_url =
     rc_get_with_fix(DVM_CONFIGRC_KEY_REGISTRY_BINARY).unwrap_or_else(|_| REGISTRY_OFFICIAL.to_string());
-  let version_registry_url =
-    rc_get_with_fix(DVM_CONFIGRC_KEY_REGISTRY_VERSION).unwrap_or_else(|_| REGISTRY_LIST_OFFICIAL.to_string());
 
-  if let Some(version) = version.clone() {
-    if version == *DVM_VERSION_CANARY {
+  let resolution = match version {
+    Some(ref version) => resolve(meta, version)?,
+    None => resolve(meta, DVM_VERSION_LATEST)?,
+  };
+  for line in resolution.explain() {
+    println!("{}", line);
+  }
+
+  let install_version = match resolution.resolved {
+    Resolved::Version(version) => version,
+    Resolved::Canary => {
+      if http::is_offline() {
+        anyhow::bail!("deno canary cannot be installed in offline mode");
+      }
       let canary_path = deno_canary_path();
       std::fs::create_dir_all(canary_path.parent().unwrap())?;
-      let hash = get_latest_canary(&binary_registry_url).expect("Failed to get latest canary");
-      let data = download_canary(&binary_registry_url, &hash)?;
-      unpack_canary(data)?;
+      let hash = get_latest_canary(&binary_registry_url)?;
+      let archive = download_canary(&binary_registry_url, &hash)?;
+      unpack_canary(fs::File::open(&archive)?)?;
 
       if !no_use {
         use_version::use_canary_bin_path(false).unwrap();
@@ -51,41 +64,19 @@ pub fn exec(_: &DvmMeta, no_use: bool, version: Option<String>) -> Result<()> {
 
       return Ok(());
     }
-  }
-
-  let install_version = match version {
-    Some(ref passed_version) if passed_version == DVM_VERSION_LATEST => {
-      println!("Checking for latest version");
-      let version = get_latest_remote_version(&version_registry_url)?;
-      println!("The latest version is v{}", version);
-      version
-    }
-    Some(ref passed_version) if passed_version == DVM_VERSION_LTS => {
-      println!("Checking for latest LTS version");
-      let version = get_latest_lts_version()?;
-      println!("The latest LTS version is v{}", version);
-      version
-    }
-    Some(ref passed_version) => {
-      Version::parse(passed_version).map_err(|_| anyhow::format_err!("Invalid semver {}", passed_version))?
-    }
-    None => {
-      println!("Checking for latest version");
-      let version = get_latest_remote_version(&version_registry_url)?;
-      println!("The latest version is v{}", version);
-      version
-    }
+    Resolved::Toolchain { name, .. } => anyhow::bail!("{} is a linked toolchain, there is nothing to install", name),
+    Resolved::System => anyhow::bail!("The system deno is not managed by dvm, there is nothing to install"),
   };
 
   let exe_path = deno_version_path(&install_version);
 
-  if exe_path.exists() {
-    println!("Version v{} is already installed", install_version);
-  } else {
-    download_and_unpack_package(
-      &compose_url_to_exec(&binary_registry_url, &install_version),
-      &install_version,
-    )?;
+  match install_from_registry(&binary_registry_url, &install_version, &Reporter::default())? {
+    Installed::Present => println!("Version v{} is already installed", install_version),
+    Installed::New { checksum } => {
+      if let Some(checksum) = checksum {
+        meta.set_checksum(install_version.to_string(), checksum);
+      }
+    }
   }
 
   if !no_use {
@@ -100,47 +91,442 @@ pub fn exec(_: &DvmMeta, no_use: bool, version: Option<String>) -> Result<()> {
   Ok(())
 }
 
-fn download_package(url: &str, version: &Version) -> Result<Vec<u8>> {
-  println!("downloading {}", &url);
+/// install several versions at once, `jobs` of them are downloaded at a time,
+/// a range installs every matching release. none of them is used afterwards.
+pub fn exec_many(meta: &mut DvmMeta, versions: &[String], jobs: usize) -> Result<()> {
+  clean_staging()?;
 
-  let response = match tinyget::get(url).send() {
-    Ok(response) => response,
-    Err(error) => {
-      println!("Network error {}", &error);
-      std::process::exit(1)
+  let binary_registry_url =
+    rc_get_with_fix(DVM_CONFIGRC_KEY_REGISTRY_BINARY).unwrap_or_else(|_| REGISTRY_OFFICIAL.to_string());
+
+  let mut targets: Vec<Version> = Vec::new();
+  for input in versions {
+    for version in expand(meta, input)? {
+      if !targets.contains(&version) {
+        targets.push(version);
+      }
+    }
+  }
+  println!(
+    "Installing {}",
+    targets
+      .iter()
+      .map(|it| format!("v{}", it))
+      .collect::<Vec<_>>()
+      .join(", ")
+  );
+
+  let multi = MultiProgress::new();
+  let next = AtomicUsize::new(0);
+  let results: Mutex<Vec<Option<Result<Installed>>>> = Mutex::new(targets.iter().map(|_| None).collect());
+  std::thread::scope(|scope| {
+    for _ in 0..jobs.min(targets.len()) {
+      scope.spawn(|| loop {
+        let index = next.fetch_add(1, Ordering::SeqCst);
+        let Some(version) = targets.get(index) else {
+          break;
+        };
+        let reporter = Reporter::multi(&multi, format!("v{}", version));
+        let result = install_from_registry(&binary_registry_url, version, &reporter);
+        results.lock().unwrap()[index] = Some(result);
+      });
     }
+  });
+  multi.clear()?;
+
+  let mut failed = 0;
+  for (version, result) in targets.iter().zip(results.into_inner().unwrap()) {
+    match result.unwrap_or_else(|| Err(anyhow::anyhow!("The install was interrupted"))) {
+      Ok(Installed::New { checksum }) => {
+        if let Some(checksum) = checksum {
+          meta.set_checksum(version.to_string(), checksum);
+        }
+        println!("{} v{}", "installed".bright_green(), version);
+      }
+      Ok(Installed::Present) => println!("{} v{}", "already installed".bright_black(), version),
+      Err(err) => {
+        failed += 1;
+        println!("{} v{}: {}", "failed".bright_red(), version, err);
+      }
+    }
+  }
+  if failed > 0 {
+    anyhow::bail!("{} of {} versions failed to install", failed, targets.len());
+  }
+
+  Ok(())
+}
+
+/// whether a version had to be installed, or was there already
+enum Installed {
+  /// the sha256 digest of the archive, if it was downloaded and verified against a published checksum
+  New {
+    checksum: Option<String>,
+  },
+  Present,
+}
+
+/// download and unpack a version from the binary registry, unless it is installed already
+fn install_from_registry(registry: &str, version: &Version, reporter: &Reporter) -> Result<Installed> {
+  if deno_version_path(version).exists() {
+    return Ok(Installed::Present);
+  }
+  // an OCI repository is not laid out by url, the archive is found through the manifest of the tag
+  let url = if oci::is_oci(registry) {
+    registry.to_string()
+  } else {
+    compose_url_to_exec(registry, &release_template(), version)
   };
+  let checksum = download_and_unpack_package(&url, version, reporter)?;
+  Ok(Installed::New { checksum })
+}
+
+/// install the deno executable in a local archive, the version is read from the executable
+pub fn exec_from_file(no_use: bool, archive: &Path, version: Option<String>) -> Result<()> {
+  clean_staging()?;
+  let archive =
+    fs::File::open(archive).map_err(|err| anyhow::anyhow!("Failed to read {}: {}", archive.display(), err))?;
+  let staging = staging_dir("from-file")?;
+  let staged_exe = staging.path().join(DENO_EXE);
+  unpack_impl(archive, staging.path().to_path_buf(), staged_exe)?;
+  install_staged(no_use, staging, version)
+}
+
+/// install a copy of a deno executable, the version is read from the executable
+pub fn exec_from_binary(no_use: bool, binary: &Path, version: Option<String>) -> Result<()> {
+  clean_staging()?;
+  let staging = staging_dir("from-binary")?;
+  let staged_exe = staging.path().join(DENO_EXE);
+  fs::copy(binary, &staged_exe).map_err(|err| anyhow::anyhow!("Failed to copy {}: {}", binary.display(), err))?;
+  set_executable(&staged_exe)?;
+  install_staged(no_use, staging, version)
+}
+
+/// register the executable in a staging directory under `versions/<version>`,
+/// the version it reports has to match the requested one if there is any.
+fn install_staged(no_use: bool, staging: TempDir, version: Option<String>) -> Result<()> {
+  let staged_exe = staging.path().join(DENO_EXE);
+  let detected = use_version::exe_version(&staged_exe)?;
+  if let Some(requested) = &version {
+    let requested = Version::parse(requested).map_err(|_| anyhow::format_err!("Invalid semver {}", requested))?;
+    use_version::check_exe(&staged_exe, &requested)?;
+  }
+  println!("Found Deno v{}", detected);
+
+  let exe_path = deno_version_path(&detected);
+  if exe_path.exists() {
+    println!("Version v{} is already installed", detected);
+  } else {
+    move_into_place(staging, &detected)?;
+  }
+
+  if !no_use {
+    use_version::use_this_bin_path(&exe_path, &detected, detected.to_string(), false)?;
+  }
+
+  Ok(())
+}
+
+fn download_package(url: &str, version: &Version, reporter: &Reporter) -> Result<TempPath> {
+  reporter.println(format!("downloading {}", http::primary(url)));
+
+  let partial_path = dvm_downloads().join(format!("{}-{}.part", version, ARCHIVE_NAME));
+  let archive = download_resumable(url, &partial_path, reporter)?;
+
+  reporter.println(format!("Deno v{} has been downloaded", &version));
+
+  Ok(archive)
+}
+
+/// stream `url` into `partial_path`, resuming with a `Range` request
+/// if a previous download of the same file was interrupted.
+/// a connection dropped in the middle of the download is resumed with exponential backoff.
+/// the complete file is removed once the returned path is dropped.
+pub fn download_resumable(url: &str, partial_path: &Path, reporter: &Reporter) -> Result<TempPath> {
+  let config = HttpConfig::current();
+  let mut attempt = 0;
+  loop {
+    match download_attempt(url, partial_path, reporter) {
+      Ok(archive) => return Ok(archive),
+      Err(err) if err.is::<Interrupted>() && attempt < config.retries => {
+        let delay = config.backoff(attempt);
+        attempt += 1;
+        reporter.eprintln(format!(
+          "{}, resuming in {:.1}s ({}/{})",
+          err,
+          delay.as_secs_f32(),
+          attempt,
+          config.retries
+        ));
+        std::thread::sleep(delay);
+      }
+      Err(err) => return Err(err),
+    }
+  }
+}
 
-  if response.status_code == 404 {
-    println!("Version has not been found, aborting");
-    std::process::exit(1)
+/// the connection was dropped in the middle of a download
+#[derive(Debug)]
+struct Interrupted(io::Error);
+
+impl std::fmt::Display for Interrupted {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "Download interrupted: {}", self.0)
+  }
+}
+
+impl std::error::Error for Interrupted {}
+
+/// the registry does not publish the requested file
+#[derive(Debug)]
+pub struct NotFound(String);
+
+impl std::fmt::Display for NotFound {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "'{}' has not been found", self.0)
   }
+}
+
+impl std::error::Error for NotFound {}
+
+fn download_attempt(url: &str, partial_path: &Path, reporter: &Reporter) -> Result<TempPath> {
+  fs::create_dir_all(partial_path.parent().unwrap())?;
+  let offset = fs::metadata(partial_path).map(|it| it.len()).unwrap_or(0);
 
-  if response.status_code >= 400 && response.status_code <= 599 {
-    println!("Download '{}' failed: {}", &url, response.status_code);
-    std::process::exit(1)
+  let range = format!("bytes={}-", offset);
+  let headers: &[(&str, &str)] = if offset > 0 { &[("Range", &range)] } else { &[] };
+  let response = http::get_with_headers(url, headers)?;
+
+  if response.status() == 416 {
+    // the partial file does not match the remote file anymore
+    fs::remove_file(partial_path)?;
+    return download_attempt(url, partial_path, reporter);
   }
 
-  println!("Version has been found");
-  println!("Deno v{} has been downloaded", &version);
+  if response.status() == 404 {
+    return Err(NotFound(http::primary(url).to_string()).into());
+  }
 
-  Ok(response.into_bytes())
+  if response.status() >= 400 {
+    anyhow::bail!("Download '{}' failed: {}", url, response.status());
+  }
+
+  let resumed = response.status() == 206;
+  if resumed && response.header("content-range").and_then(content_range_start) != Some(offset) {
+    // appending a range that does not start where the partial file ends would corrupt it
+    reporter.eprintln(format!(
+      "{} resumed the download at the wrong offset, downloading it again",
+      http::primary(url)
+    ));
+    drop(response);
+    fs::remove_file(partial_path)?;
+    return download_attempt(url, partial_path, reporter);
+  }
+  let offset = if resumed { offset } else { 0 };
+  let total = response
+    .header("content-range")
+    .and_then(content_range_total)
+    .or_else(|| {
+      response
+        .header("content-length")
+        .and_then(|it| it.trim().parse::<u64>().ok())
+        .map(|it| it + offset)
+    });
+  if resumed {
+    reporter.println(format!("Resuming download from {}", HumanBytes(offset)));
+  }
+
+  let mut file = fs::OpenOptions::new()
+    .create(true)
+    .write(true)
+    .append(resumed)
+    .truncate(!resumed)
+    .open(partial_path)?;
+
+  let progress = reporter.progress_bar(total);
+  progress.set_position(offset);
+  let mut reader = response.into_reader();
+  let mut buffer = vec![0; DOWNLOAD_BUFFER_SIZE];
+  loop {
+    let read = match reader.read(&mut buffer) {
+      Ok(0) => break,
+      Ok(read) => read,
+      Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+      Err(err) => {
+        progress.abandon();
+        return Err(Interrupted(err).into());
+      }
+    };
+    file.write_all(&buffer[..read])?;
+    progress.inc(read as u64);
+  }
+  file.flush()?;
+  progress.finish_and_clear();
+  drop(file);
+
+  // the archive is hashed and unpacked from the disk rather than read into memory
+  Ok(TempPath::from_path(partial_path))
 }
 
-fn compose_url_to_exec(registry: &str, version: &Version) -> String {
-  format!("{}release/v{}/{}", registry, version, ARCHIVE_NAME)
+/// the complete length from a `Content-Range: bytes 100-999/1000` header
+fn content_range_total(content_range: &str) -> Option<u64> {
+  content_range.rsplit_once('/')?.1.trim().parse().ok()
 }
 
-fn download_and_unpack_package(url: &str, version: &Version) -> Result<()> {
-  let archive_data = download_package(url, version)?;
-  if let Err(err) = unpack(archive_data, version) {
-    eprintln!("Failed to unpack Deno v{}: {}", version, err);
-    eprintln!("Removing the corrupted archive and retrying download");
-    remove_version_dir(version)?;
+/// the first byte from a `Content-Range: bytes 100-999/1000` header
+fn content_range_start(content_range: &str) -> Option<u64> {
+  content_range
+    .trim()
+    .strip_prefix("bytes ")?
+    .split_once('-')?
+    .0
+    .trim()
+    .parse()
+    .ok()
+}
 
-    let archive_data = download_package(url, version)?;
-    if let Err(retry_err) = unpack(archive_data, version) {
-      remove_version_dir(version)?;
+fn compose_url_to_exec(registry: &str, template: &str, version: &Version) -> String {
+  http::join(registry, &expand_template(template, &version.to_string(), ARCHIVE_NAME))
+}
+
+/// where a release archive is in the binary registry, `registry_release_template` in the `.dvmrc`
+pub fn release_template() -> String {
+  rc_get(DVM_CONFIGRC_KEY_REGISTRY_RELEASE_TEMPLATE)
+    .ok()
+    .filter(|it| !it.is_empty())
+    .unwrap_or_else(|| REGISTRY_RELEASE_TEMPLATE_DEFAULT.to_string())
+}
+
+/// whether an archive without a published checksum is rejected, `require_checksum` in the `.dvmrc`
+pub fn require_checksum() -> bool {
+  rc_get(DVM_CONFIGRC_KEY_REQUIRE_CHECKSUM)
+    .is_ok_and(|it| matches!(it.trim().to_ascii_lowercase().as_str(), "1" | "true"))
+}
+
+/// where a canary archive is in the binary registry, `registry_canary_template` in the `.dvmrc`
+pub fn canary_template() -> String {
+  rc_get(DVM_CONFIGRC_KEY_REGISTRY_CANARY_TEMPLATE)
+    .ok()
+    .filter(|it| !it.is_empty())
+    .unwrap_or_else(|| REGISTRY_CANARY_TEMPLATE_DEFAULT.to_string())
+}
+
+/// the path of an archive in a registry, filling in `{version}`, `{target}` and `{archive}` of the template.
+/// `{version}` is the version without a leading `v`, or the commit hash of a canary.
+pub fn expand_template(template: &str, version: &str, archive: &str) -> String {
+  let target = archive.trim_start_matches("deno-").trim_end_matches(".zip");
+  template
+    .replace("{version}", version)
+    .replace("{target}", target)
+    .replace("{archive}", archive)
+}
+
+/// fetch the `.sha256sum` file published next to an archive,
+/// None if the registry does not publish one for this archive and none is required.
+pub fn fetch_checksum(url: &str) -> Result<Option<String>> {
+  let checksum_url = http::join(url, ".sha256sum");
+  let response = http::get(&checksum_url)?;
+  if response.status() == 404 {
+    if require_checksum() {
+      anyhow::bail!(
+        "No checksum published for {} and `require_checksum` is set",
+        http::primary(url)
+      );
+    }
+    return Ok(None);
+  }
+  if response.status() >= 400 {
+    anyhow::bail!("Download '{}' failed: {}", checksum_url, response.status());
+  }
+
+  let checksum = parse_checksum(&String::from_utf8_lossy(&response.into_bytes()?))
+    .ok_or_else(|| anyhow::anyhow!("No sha256 digest found in '{}'", checksum_url))?;
+  Ok(Some(checksum))
+}
+
+/// find the sha256 digest in the content of a `.sha256sum` file,
+/// which is either `shasum` output or PowerShell `Get-FileHash` output.
+fn parse_checksum(content: &str) -> Option<String> {
+  content
+    .replace('\0', "")
+    .split(|ch: char| !ch.is_ascii_alphanumeric())
+    .find(|token| token.len() == 64 && token.chars().all(|ch| ch.is_ascii_hexdigit()))
+    .map(|token| token.to_ascii_lowercase())
+}
+
+/// verify the archive against the published checksum,
+/// returns the verified digest, or None if there is nothing to verify against.
+pub fn verify_checksum(
+  url: &str,
+  archive: impl Read,
+  expected: Option<&str>,
+  reporter: &Reporter,
+) -> Result<Option<String>> {
+  let Some(expected) = expected else {
+    reporter.eprintln(format!(
+      "{} no checksum published for {}, skipping verification",
+      "warning:".yellow(),
+      http::primary(url)
+    ));
+    return Ok(None);
+  };
+
+  let actual = sha256_reader(archive)?;
+  if actual != expected {
+    anyhow::bail!(
+      "Checksum mismatch for {}: expected {}, got {}",
+      http::primary(url),
+      expected,
+      actual
+    );
+  }
+
+  reporter.println(format!("Checksum verified: sha256 {}", actual));
+  Ok(Some(actual))
+}
+
+fn download_and_unpack_package(url: &str, version: &Version, reporter: &Reporter) -> Result<Option<String>> {
+  // only verified archives are cached, their checksum was recorded when they were downloaded
+  if let Some((archive, _)) = archives::get(version, ARCHIVE_NAME)? {
+    reporter.println(format!("Using the cached archive of Deno v{}", version));
+    match unpack(archive, version) {
+      Ok(_) => return Ok(None),
+      Err(err) => reporter.eprintln(format!(
+        "Failed to unpack the cached archive of Deno v{}: {}",
+        version, err
+      )),
+    }
+  }
+  if http::is_offline() {
+    anyhow::bail!(
+      "deno v{} is not installed and cannot be downloaded in offline mode",
+      version
+    );
+  }
+
+  let expected = if oci::is_oci(url) { None } else { fetch_checksum(url)? };
+  let download = || -> Result<(TempPath, Option<String>)> {
+    if oci::is_oci(url) {
+      let (archive_data, digest) = oci::pull(url, version, ARCHIVE_NAME, reporter)?;
+      fs::create_dir_all(dvm_downloads())?;
+      let mut archive = tempfile::NamedTempFile::new_in(dvm_downloads())?;
+      archive.write_all(&archive_data)?;
+      return Ok((archive.into_temp_path(), Some(digest)));
+    }
+    let archive = download_package(url, version, reporter)?;
+    let checksum = verify_checksum(url, fs::File::open(&archive)?, expected.as_deref(), reporter)?;
+    Ok((archive, checksum))
+  };
+
+  let (mut archive, mut checksum) = download()?;
+  if let Err(err) = unpack(fs::File::open(&archive)?, version) {
+    reporter.eprintln(format!("Failed to unpack Deno v{}: {}", version, err));
+    reporter.eprintln("Removing the corrupted archive and retrying download");
+
+    // the retry downloads to the same path, which the old one would remove once dropped
+    drop(archive);
+    (archive, checksum) = download()?;
+    if let Err(retry_err) = unpack(fs::File::open(&archive)?, version) {
       return Err(anyhow::anyhow!(
         "Failed to unpack Deno v{} after retry: {}",
         version,
@@ -149,90 +535,142 @@ fn download_and_unpack_package(url: &str, version: &Version) -> Result<()> {
     }
   }
 
-  Ok(())
+  // the install succeeded already, a full disk only costs the next download.
+  // an archive without a published checksum is not cached, `dvm cache export` would pass it on as verified
+  if let Some(checksum) = &checksum {
+    if let Err(err) = archives::put(version, ARCHIVE_NAME, &archive, checksum) {
+      reporter.eprintln(format!("Failed to cache the archive of Deno v{}: {}", version, err));
+    }
+  }
+
+  Ok(checksum)
 }
 
-fn remove_version_dir(version: &Version) -> Result<()> {
-  let version_dir = dvm_root().join(format!("{}/{}", DVM_CACHE_PATH_PREFIX, version));
-  if version_dir.exists() {
-    fs::remove_dir_all(version_dir)?;
+/// create a staging directory under `dvm_root()`,
+/// it is removed automatically unless it gets moved into place.
+fn staging_dir(name: &str) -> Result<TempDir> {
+  let staging_root = dvm_staging();
+  fs::create_dir_all(&staging_root)?;
+  Ok(
+    tempfile::Builder::new()
+      .prefix(&format!("{}-", name))
+      .tempdir_in(staging_root)?,
+  )
+}
+
+/// remove staging directories left behind by interrupted installs
+pub fn clean_staging() -> Result<()> {
+  let Ok(entries) = fs::read_dir(dvm_staging()) else {
+    return Ok(());
+  };
+
+  for entry in entries.flatten() {
+    println!(
+      "Removing interrupted install {}",
+      entry.file_name().to_string_lossy().bright_black()
+    );
+    if entry.file_type()?.is_dir() {
+      fs::remove_dir_all(entry.path())?;
+    } else {
+      fs::remove_file(entry.path())?;
+    }
   }
+
   Ok(())
 }
 
-fn unpack(archive_data: Vec<u8>, version: &Version) -> Result<PathBuf> {
+/// unpack into a staging directory, and only move it to `versions/<version>`
+/// after the executable has been checked, so a version directory is never half-populated.
+fn unpack(archive: impl Read + Seek, version: &Version) -> Result<PathBuf> {
+  let staging = staging_dir(&version.to_string())?;
+  let staged_exe = staging.path().join(DENO_EXE);
+  unpack_impl(archive, staging.path().to_path_buf(), staged_exe.clone())?;
+  use_version::check_exe(&staged_exe, version)?;
+  move_into_place(staging, version)
+}
+
+/// move a staged version directory to `versions/<version>`, replacing an existing one
+fn move_into_place(staging: TempDir, version: &Version) -> Result<PathBuf> {
   let version_dir = dvm_root().join(format!("{}/{}", DVM_CACHE_PATH_PREFIX, version));
-  fs::create_dir_all(&version_dir)?;
-  let exe_path = deno_version_path(version);
+  if version_dir.exists() {
+    fs::remove_dir_all(&version_dir)?;
+  }
+  fs::create_dir_all(version_dir.parent().unwrap())?;
+  fs::rename(staging.path(), &version_dir)?;
+  // the staging directory has been moved, nothing is left to clean up
+  let _ = staging.keep();
 
-  unpack_impl(archive_data, version_dir, exe_path)
+  Ok(version_dir)
 }
 
-fn unpack_canary(archive_data: Vec<u8>) -> Result<PathBuf> {
+fn unpack_canary(archive: impl Read + Seek) -> Result<PathBuf> {
   let canary_dir = dvm_root().join(DVM_CANARY_PATH_PREFIX);
   fs::create_dir_all(&canary_dir)?;
   let exe_path = deno_canary_path();
 
+  let staging = staging_dir(DVM_VERSION_CANARY)?;
+  let staged_exe = staging.path().join(DENO_EXE);
+  unpack_impl(archive, staging.path().to_path_buf(), staged_exe.clone())?;
+
   if exe_path.exists() {
     fs::remove_file(exe_path.clone())?;
   }
+  fs::rename(staged_exe, exe_path)?;
 
-  unpack_impl(archive_data, canary_dir, exe_path)
+  Ok(canary_dir)
 }
 
-fn unpack_impl(archive_data: Vec<u8>, version_dir: PathBuf, path: PathBuf) -> Result<PathBuf> {
+fn unpack_impl(archive: impl Read + Seek, version_dir: PathBuf, path: PathBuf) -> Result<PathBuf> {
   let archive_ext = Path::new(ARCHIVE_NAME)
     .extension()
     .and_then(|ext| ext.to_str())
     .unwrap();
-  let unpack_status = match archive_ext {
-    "zip" if cfg!(windows) => {
-      let archive_path = version_dir.join("deno.zip");
-      fs::write(&archive_path, &archive_data)?;
-      Command::new("powershell.exe")
-        .arg("-NoLogo")
-        .arg("-NoProfile")
-        .arg("-NonInteractive")
-        .arg("-Command")
-        .arg(
-          "& {
-            param($Path, $DestinationPath)
-            trap { $host.ui.WriteErrorLine($_.Exception); exit 1 }
-            Add-Type -AssemblyName System.IO.Compression.FileSystem
-            [System.IO.Compression.ZipFile]::ExtractToDirectory(
-              $Path,
-              $DestinationPath
-            );
-          }",
-        )
-        .arg("-Path")
-        .arg(format!("'{}'", &archive_path.to_str().unwrap()))
-        .arg("-DestinationPath")
-        .arg(format!("'{}'", &version_dir.to_str().unwrap()))
-        .spawn()?
-        .wait()?
-    }
-    "zip" => {
-      let archive_path = version_dir.join("deno.zip");
-      fs::write(&archive_path, &archive_data)?;
-      Command::new("unzip")
-        .current_dir(&version_dir)
-        .arg(archive_path)
-        .spawn()?
-        .wait()?
-    }
+  match archive_ext {
+    "zip" => extract_exe_from_zip(archive, &path)?,
     ext => anyhow::bail!("Unsupported archive type: '{}'", ext),
   };
-  if !unpack_status.success() {
-    anyhow::bail!("Failed to unpack archive");
-  }
   if !path.exists() {
     anyhow::bail!("Unpacked archive did not contain {}", path.display());
   }
   Ok(version_dir)
 }
 
-fn download_canary(registry: &str, hash: &str) -> Result<Vec<u8>> {
+/// extract the deno executable, and nothing else, from a zip archive to `path`.
+/// entries whose names escape the archive root (e.g. `../deno`) are rejected.
+fn extract_exe_from_zip(archive: impl Read + Seek, path: &Path) -> Result<()> {
+  let mut archive = ZipArchive::new(archive)?;
+
+  for i in 0..archive.len() {
+    let mut entry = archive.by_index(i)?;
+    let Some(name) = entry.enclosed_name() else {
+      anyhow::bail!("Archive entry '{}' has an unsafe path", entry.name());
+    };
+    if !entry.is_file() || name.file_name().and_then(|it| it.to_str()) != Some(DENO_EXE) {
+      continue;
+    }
+
+    let mut file = fs::File::create(path)?;
+    io::copy(&mut entry, &mut file)?;
+    set_executable(path)?;
+    return Ok(());
+  }
+
+  anyhow::bail!("Archive did not contain {}", DENO_EXE)
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path) -> Result<()> {
+  use std::os::unix::fs::PermissionsExt;
+  fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+  Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_executable(_: &Path) -> Result<()> {
+  Ok(())
+}
+
+fn download_canary(registry: &str, hash: &str) -> Result<TempPath> {
   // TODO: remove this when deno canary support m1 chip,
   let archive_name = if ARCHIVE_NAME == "deno-aarch64-apple-darwin.zip" {
     "deno-x86_64-apple-darwin.zip"
@@ -240,19 +678,108 @@ fn download_canary(registry: &str, hash: &str) -> Result<Vec<u8>> {
     ARCHIVE_NAME
   };
 
-  let url = format!("{}canary/{}/{}", registry, hash, archive_name);
+  let url = http::join(registry, &expand_template(&canary_template(), hash, archive_name));
 
-  let resp = tinyget::get(url).send()?;
-  Ok(resp.into_bytes())
+  let expected = fetch_checksum(&url)?;
+  let partial_path = dvm_downloads().join(format!("{}-{}-{}.part", DVM_VERSION_CANARY, hash, archive_name));
+  let reporter = Reporter::default();
+  let archive = download_resumable(&url, &partial_path, &reporter)?;
+  verify_checksum(&url, fs::File::open(&archive)?, expected.as_deref(), &reporter)?;
+  Ok(archive)
+}
+
+#[test]
+fn test_parse_checksum() {
+  let digest = "4f3c2b1a".repeat(8);
+
+  assert_eq!(
+    parse_checksum(&format!("{}  deno-x86_64-unknown-linux-gnu.zip\n", digest)),
+    Some(digest.clone())
+  );
+  assert_eq!(
+    parse_checksum(&format!(
+      "\r\nAlgorithm : SHA256\r\nHash      : {}\r\nPath      : D:\\a\\deno\\deno-x86_64-pc-windows-msvc.zip\r\n",
+      digest.to_ascii_uppercase()
+    )),
+    Some(digest)
+  );
+  assert_eq!(parse_checksum("Not Found"), None);
+}
+
+#[cfg(test)]
+fn zip_with_entries(entries: &[(&str, &[u8])]) -> Vec<u8> {
+  let mut writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+  for (name, content) in entries {
+    writer
+      .start_file(*name, zip::write::SimpleFileOptions::default())
+      .unwrap();
+    writer.write_all(content).unwrap();
+  }
+  writer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn test_extract_exe_from_zip() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join(DENO_EXE);
+  let archive = zip_with_entries(&[("README.md", b"readme"), (DENO_EXE, b"binary")]);
+
+  extract_exe_from_zip(io::Cursor::new(archive), &path).unwrap();
+
+  assert_eq!(fs::read(&path).unwrap(), b"binary");
+  assert!(!dir.path().join("README.md").exists());
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::PermissionsExt;
+    assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o755);
+  }
+}
+
+#[test]
+fn test_extract_exe_from_zip_rejects_path_traversal() {
+  let dir = tempfile::tempdir().unwrap();
+  let path = dir.path().join(DENO_EXE);
+  let archive = zip_with_entries(&[(&format!("../{}", DENO_EXE), b"binary")]);
+
+  assert!(extract_exe_from_zip(io::Cursor::new(archive), &path).is_err());
+  assert!(!path.exists());
+
+  let archive = zip_with_entries(&[("README.md", b"readme")]);
+  assert!(extract_exe_from_zip(io::Cursor::new(archive), &path).is_err());
+}
+
+#[test]
+fn test_content_range_total() {
+  assert_eq!(content_range_total("bytes 100-999/1000"), Some(1000));
+  assert_eq!(content_range_total("bytes 100-999/*"), None);
+  assert_eq!(content_range_total("bytes"), None);
+}
+
+#[test]
+fn test_content_range_start() {
+  assert_eq!(content_range_start("bytes 100-999/1000"), Some(100));
+  assert_eq!(content_range_start("bytes */1000"), None);
+}
+
+#[test]
+fn test_sha256_hex() {
+  use crate::utils::sha256_hex;
+
+  assert_eq!(
+    sha256_hex(b"abc"),
+    "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+  );
+  assert_eq!(sha256_reader(&b"abc"[..]).unwrap(), sha256_hex(b"abc"));
 }
 
 #[test]
 fn test_compose_url_to_exec() {
   use crate::consts::REGISTRY_OFFICIAL;
   use asserts_rs::asserts_eq_one_of;
+  use cfg_if::cfg_if;
 
   let v = Version::parse("1.7.0").unwrap();
-  let url = compose_url_to_exec(REGISTRY_OFFICIAL, &v);
+  let url = compose_url_to_exec(REGISTRY_OFFICIAL, REGISTRY_RELEASE_TEMPLATE_DEFAULT, &v);
 
   cfg_if! {
     if #[cfg(windows)] {
@@ -288,3 +815,25 @@ fn test_compose_url_to_exec() {
     }
   }
 }
+
+#[test]
+fn test_expand_template() {
+  let archive = "deno-x86_64-unknown-linux-gnu.zip";
+
+  assert_eq!(
+    expand_template(REGISTRY_RELEASE_TEMPLATE_DEFAULT, "2.1.4", archive),
+    "release/v2.1.4/deno-x86_64-unknown-linux-gnu.zip"
+  );
+  assert_eq!(
+    expand_template(REGISTRY_CANARY_TEMPLATE_DEFAULT, "3f2a9c1", archive),
+    "canary/3f2a9c1/deno-x86_64-unknown-linux-gnu.zip"
+  );
+  assert_eq!(
+    expand_template("releases/download/v{version}/{archive}", "2.1.4", archive),
+    "releases/download/v2.1.4/deno-x86_64-unknown-linux-gnu.zip"
+  );
+  assert_eq!(
+    expand_template("deno/{version}/{target}/deno.zip", "2.1.4", archive),
+    "deno/2.1.4/x86_64-unknown-linux-gnu/deno.zip"
+  );
+}
@@ -0,0 +1,54 @@
+use crate::commands::use_version::exe_version;
+use crate::consts::{DVM_VERSION_CANARY, DVM_VERSION_LATEST, DVM_VERSION_LTS, DVM_VERSION_SELF, DVM_VERSION_SYSTEM};
+use crate::meta::DvmMeta;
+use anyhow::Result;
+use colored::Colorize;
+use semver::VersionReq;
+use std::path::PathBuf;
+
+pub fn exec(meta: &mut DvmMeta, name: Option<String>, path: Option<PathBuf>) -> Result<()> {
+  let (Some(name), Some(path)) = (name, path) else {
+    for toolchain in &meta.toolchains {
+      println!("{} -> {}", toolchain.name.bright_blue(), toolchain.path.display());
+    }
+    return Ok(());
+  };
+
+  check_name(meta, &name)?;
+  let path = path
+    .canonicalize()
+    .map_err(|err| anyhow::anyhow!("Failed to find {}: {}", path.display(), err))?;
+  let version = exe_version(&path)?;
+
+  meta.set_toolchain(name.clone(), path.clone());
+  println!("Linked {} -> {} (deno {})", name.bright_blue(), path.display(), version);
+  println!("Use it with {}", format!("dvm use {}", name).bright_green());
+  Ok(())
+}
+
+/// a toolchain name must not be mistaken for a version, a range, a tag or an alias
+fn check_name(meta: &DvmMeta, name: &str) -> Result<()> {
+  let reserved = [
+    DVM_VERSION_CANARY,
+    DVM_VERSION_LATEST,
+    DVM_VERSION_LTS,
+    DVM_VERSION_SELF,
+    DVM_VERSION_SYSTEM,
+  ];
+  if reserved.contains(&name) || VersionReq::parse(name).is_ok() {
+    anyhow::bail!(
+      "`{}` is a version or a tag, choose another name for the toolchain",
+      name
+    );
+  }
+  if !name
+    .chars()
+    .all(|it| it.is_ascii_alphanumeric() || matches!(it, '-' | '_' | '.'))
+  {
+    anyhow::bail!("a toolchain name may only contain letters, digits, `-`, `_` and `.`");
+  }
+  if meta.has_alias(name) {
+    anyhow::bail!("`{}` is already an alias, choose another name for the toolchain", name);
+  }
+  Ok(())
+}
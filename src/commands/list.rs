@@ -1,3 +1,4 @@
+use crate::utils::is_semver;
 use crate::version::{current_version, local_versions, remote_versions};
 use anyhow::Result;
 use semver::Version;
@@ -11,16 +12,20 @@ pub fn exec() -> Result<()> {
 }
 
 pub fn exec_remote() -> Result<()> {
-  let versions = remote_versions().unwrap();
+  let versions = remote_versions()?;
 
   print_versions(versions);
   Ok(())
 }
 
-fn print_versions(mut versions: Vec<String>) {
+fn print_versions(versions: Vec<String>) {
   let current_version = current_version().unwrap_or_default();
 
+  // names of linked toolchains are listed after the versions
+  let (mut versions, mut toolchains): (Vec<String>, Vec<String>) = versions.into_iter().partition(|it| is_semver(it));
   versions.sort_by(|a, b| sort_semver_version(b, a).reverse());
+  toolchains.sort();
+  versions.extend(toolchains);
 
   for v in &versions {
     if *v == current_version {
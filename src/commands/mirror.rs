@@ -0,0 +1,257 @@
+use super::install::{
+  canary_template, download_resumable, expand_template, fetch_checksum, release_template, verify_checksum, NotFound,
+};
+use crate::archives::{self, checksum_path};
+use crate::cli::MirrorCommands;
+use crate::configrc::rc_get_with_fix;
+use crate::consts::{
+  ARCHIVE_NAME, DENO_TARGETS, DVM_CONFIGRC_KEY_REGISTRY_BINARY, DVM_CONFIGRC_KEY_REGISTRY_VERSION,
+  REGISTRY_LATEST_CANARY_PATH, REGISTRY_LATEST_RELEASE_PATH, REGISTRY_VERSIONS_PATH,
+};
+use crate::http;
+use crate::oci;
+use crate::utils::{copy_atomic, dvm_downloads, sha256_hex, write_atomic, Reporter};
+use crate::version::{fetch_versions_json, get_latest_canary};
+use anyhow::Result;
+use colored::Colorize;
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// the part of `versions.json` dvm reads
+#[derive(Debug, Deserialize, Serialize)]
+struct VersionsJson {
+  cli: Vec<String>,
+}
+
+enum Outcome {
+  Downloaded,
+  Copied,
+  Present,
+  NotPublished,
+  /// mirrored without a `.sha256sum`, the registry publishes none to verify it against
+  Unverified,
+}
+
+#[derive(Default)]
+struct Summary {
+  downloaded: usize,
+  copied: usize,
+  present: usize,
+  not_published: usize,
+  unverified: usize,
+}
+
+impl Summary {
+  fn add(&mut self, outcome: Outcome) {
+    match outcome {
+      Outcome::Downloaded => self.downloaded += 1,
+      Outcome::Copied => self.copied += 1,
+      Outcome::Present => self.present += 1,
+      Outcome::NotPublished => self.not_published += 1,
+      Outcome::Unverified => self.unverified += 1,
+    }
+  }
+}
+
+pub fn exec(command: MirrorCommands) -> Result<()> {
+  match command {
+    MirrorCommands::Sync {
+      range,
+      targets,
+      dest,
+      canary,
+    } => sync(&range, &targets, &dest, canary),
+  }
+}
+
+fn sync(range: &str, targets: &[String], dest: &Path, canary: bool) -> Result<()> {
+  let required = VersionReq::parse(range).map_err(|_| anyhow::anyhow!("Invalid semver range {}", range))?;
+  let archive_names = target_archives(targets)?;
+  let registry = rc_get_with_fix(DVM_CONFIGRC_KEY_REGISTRY_BINARY)?;
+  let versions_url = rc_get_with_fix(DVM_CONFIGRC_KEY_REGISTRY_VERSION)?;
+  if oci::is_oci(&registry) {
+    anyhow::bail!("Mirroring the OCI registry {} is not supported", registry);
+  }
+
+  let released: VersionsJson = serde_json::from_str(&fetch_versions_json(&versions_url)?)
+    .map_err(|err| anyhow::anyhow!("Failed to parse {}: {}", versions_url, err))?;
+  let mut versions: Vec<Version> = released
+    .cli
+    .iter()
+    .filter_map(|it| Version::parse(it.trim_start_matches('v')).ok())
+    .filter(|it| required.matches(it))
+    .collect();
+  versions.sort();
+  versions.dedup();
+  if versions.is_empty() {
+    anyhow::bail!("No release matches {}", range);
+  }
+
+  fs::create_dir_all(dest)?;
+  let mut summary = Summary::default();
+  let release_template = release_template();
+  for version in &versions {
+    for name in &archive_names {
+      let url = http::join(
+        &registry,
+        &expand_template(&release_template, &version.to_string(), name),
+      );
+      let path = dest.join(archives::relative_path(version, name));
+      summary.add(mirror_archive(&url, &path, || archives::get(version, name))?);
+    }
+  }
+
+  if canary {
+    let hash = get_latest_canary(&registry)?;
+    let canary_template = canary_template();
+    for name in &archive_names {
+      let url = http::join(&registry, &expand_template(&canary_template, &hash, name));
+      let path = dest.join("canary").join(&hash).join(name);
+      summary.add(mirror_archive(&url, &path, || Ok(None))?);
+    }
+    write_atomic(&dest.join(REGISTRY_LATEST_CANARY_PATH), format!("{}\n", hash))?;
+  }
+
+  write_index(dest)?;
+  println!(
+    "Mirrored {} into {}: {} downloaded, {} from the archive cache, {} already present, {} not published, {} without a published checksum",
+    range,
+    dest.display(),
+    summary.downloaded,
+    summary.copied,
+    summary.present,
+    summary.not_published,
+    summary.unverified
+  );
+  println!(
+    "Serve {} over http and set `registry_binary` and `registry_version` to it",
+    dest.display()
+  );
+  Ok(())
+}
+
+/// the archive names of the targets, the current target if none is given
+fn target_archives(targets: &[String]) -> Result<Vec<&'static str>> {
+  if targets.is_empty() {
+    return Ok(vec![ARCHIVE_NAME]);
+  }
+
+  let mut archive_names = Vec::new();
+  for target in targets.iter().map(|it| it.trim()) {
+    let name = DENO_TARGETS
+      .iter()
+      .find(|(short, name)| *short == target || *name == target || *name == format!("deno-{}.zip", target))
+      .map(|(_, name)| *name)
+      .ok_or_else(|| {
+        let known: Vec<&str> = DENO_TARGETS.iter().map(|(short, _)| *short).collect();
+        anyhow::anyhow!("Unknown target {}, expected one of {}", target, known.join(", "))
+      })?;
+    if !archive_names.contains(&name) {
+      archive_names.push(name);
+    }
+  }
+  Ok(archive_names)
+}
+
+/// put the archive and its checksum at `path` unless both are there already,
+/// taking it from the archive cache when possible.
+/// the checksum is only written when the registry published one, the clients verify against it.
+fn mirror_archive(
+  url: &str,
+  path: &Path,
+  cached: impl FnOnce() -> Result<Option<(fs::File, String)>>,
+) -> Result<Outcome> {
+  let checksum = checksum_path(path);
+  let primary = http::primary(url);
+  if path.is_file() && checksum.is_file() {
+    println!("{} {}", "present".bright_black(), primary);
+    return Ok(Outcome::Present);
+  }
+  // an archive mirrored without a checksum is only downloaded again once one is published
+  if path.is_file() && fetch_checksum(url)?.is_none() {
+    println!("{} {} {}", "present".bright_black(), primary, "(unverified)".yellow());
+    return Ok(Outcome::Unverified);
+  }
+
+  // a download is removed once it has been copied into the mirror
+  let mut download = None;
+  let (archive, digest, outcome) = match cached()? {
+    Some((archive, digest)) => {
+      println!("Copying {} from the archive cache", primary);
+      (archive, Some(digest), Outcome::Copied)
+    }
+    None => {
+      println!("downloading {}", primary);
+      let expected = fetch_checksum(url)?;
+      let partial_path = dvm_downloads().join(format!("mirror-{}.part", &sha256_hex(url.as_bytes())[..16]));
+      let downloaded = match download_resumable(url, &partial_path, &Reporter::default()) {
+        Ok(downloaded) => download.insert(downloaded),
+        Err(err) if err.is::<NotFound>() => {
+          println!("{} is not published, skipping", primary);
+          return Ok(Outcome::NotPublished);
+        }
+        Err(err) => return Err(err),
+      };
+      let digest = verify_checksum(
+        url,
+        fs::File::open(&*downloaded)?,
+        expected.as_deref(),
+        &Reporter::default(),
+      )?;
+      (fs::File::open(&*downloaded)?, digest, Outcome::Downloaded)
+    }
+  };
+
+  let name = path.file_name().unwrap_or_default().to_string_lossy();
+  fs::create_dir_all(path.parent().unwrap())?;
+  copy_atomic(path, archive)?;
+  match digest {
+    Some(digest) => {
+      write_atomic(&checksum, format!("{}  {}\n", digest, name))?;
+      Ok(outcome)
+    }
+    None => Ok(Outcome::Unverified),
+  }
+}
+
+/// write `versions.json` and `release-latest.txt` for the releases in the mirror
+fn write_index(dest: &Path) -> Result<()> {
+  let mut versions: Vec<Version> = archives::list_in(dest).into_iter().map(|it| it.version).collect();
+  versions.sort_unstable_by(|a, b| b.cmp(a));
+  versions.dedup();
+
+  let index = VersionsJson {
+    cli: versions.iter().map(|it| format!("v{}", it)).collect(),
+  };
+  write_atomic(
+    &dest.join(REGISTRY_VERSIONS_PATH),
+    serde_json::to_string_pretty(&index)?,
+  )?;
+  if let Some(latest) = versions.iter().find(|it| it.pre.is_empty()) {
+    write_atomic(&dest.join(REGISTRY_LATEST_RELEASE_PATH), format!("v{}\n", latest))?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn targets_are_mapped_to_archive_names() {
+    let targets = |it: &[&str]| target_archives(&it.iter().map(|it| it.to_string()).collect::<Vec<_>>());
+
+    assert_eq!(
+      targets(&["linux-x64", "darwin-arm64", "linux-x64"]).unwrap(),
+      vec!["deno-x86_64-unknown-linux-gnu.zip", "deno-aarch64-apple-darwin.zip"]
+    );
+    assert_eq!(
+      targets(&["x86_64-pc-windows-msvc"]).unwrap(),
+      vec!["deno-x86_64-pc-windows-msvc.zip"]
+    );
+    assert_eq!(targets(&[]).unwrap(), vec![ARCHIVE_NAME]);
+    assert!(targets(&["solaris-sparc"]).is_err());
+  }
+}
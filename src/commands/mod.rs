@@ -1,5 +1,6 @@
 pub mod activate;
 pub mod alias;
+pub mod cache;
 pub mod clean;
 pub mod completions;
 pub mod deactivate;
@@ -7,8 +8,13 @@ pub mod doctor;
 pub mod exec;
 pub mod info;
 pub mod install;
+pub mod link;
 pub mod list;
+pub mod mirror;
+pub mod ping;
 pub mod registry;
+pub mod resolve;
+pub mod serve;
 pub mod uninstall;
 pub mod update;
 pub mod upgrade;
@@ -0,0 +1,165 @@
+use crate::consts::REGISTRY_LATEST_RELEASE_PATH;
+use crate::credentials::redact_url;
+use crate::http;
+use crate::meta::Registry;
+use crate::oci;
+use crate::version::latest_version_from_versions_json;
+use crate::DvmMeta;
+use anyhow::Result;
+use colored::Colorize;
+use semver::Version;
+use std::time::{Duration, Instant};
+
+/// What pinging a registry found out.
+struct Health {
+  /// how long fetching `release-latest.txt` took
+  latency: Duration,
+  /// the version in `release-latest.txt`
+  latest: Version,
+  /// the highest stable version in `versions.json`
+  listed: Version,
+}
+
+impl Health {
+  fn is_consistent(&self) -> bool {
+    self.latest == self.listed
+  }
+}
+
+/// the predefined registries and the ones saved with `dvm registry add`
+fn known_registries(meta: &DvmMeta) -> Vec<Registry> {
+  [Registry::official(), Registry::cn()]
+    .into_iter()
+    .chain(meta.registries.iter().cloned())
+    .collect()
+}
+
+/// ping the first mirror of the binary and the version registry, once and without retries
+fn ping(registry: &Registry) -> Result<Health> {
+  if oci::is_oci(&registry.binary) {
+    // the tags are both the releases and the version list of an OCI repository
+    let started = Instant::now();
+    let versions = oci::versions(&registry.binary)?;
+    let latency = started.elapsed();
+    let latest = versions
+      .into_iter()
+      .find(|it| it.pre.is_empty())
+      .ok_or_else(|| anyhow::anyhow!("{} has no release tags", redact_url(&registry.binary)))?;
+    return Ok(Health {
+      latency,
+      listed: latest.clone(),
+      latest,
+    });
+  }
+
+  let url = format!("{}{}", http::primary(&registry.binary), REGISTRY_LATEST_RELEASE_PATH);
+  let started = Instant::now();
+  let latest = fetch_text(&url)?;
+  let latency = started.elapsed();
+  let latest = Version::parse(latest.trim().trim_start_matches('v'))
+    .map_err(|_| anyhow::anyhow!("{} holds no version", redact_url(&url)))?;
+
+  let listed = latest_version_from_versions_json(&fetch_text(http::primary(&registry.version))?)?;
+  Ok(Health {
+    latency,
+    latest,
+    listed,
+  })
+}
+
+fn fetch_text(url: &str) -> Result<String> {
+  let response = http::probe(url)?;
+  if response.status() >= 400 {
+    anyhow::bail!("{} answered {}", redact_url(url), response.status());
+  }
+  response.into_string()
+}
+
+/// ping every known registry at once, in the order they are known
+fn ping_all(meta: &DvmMeta) -> Vec<(Registry, Result<Health>)> {
+  let registries = known_registries(meta);
+  std::thread::scope(|scope| {
+    let pings: Vec<_> = registries
+      .iter()
+      .map(|registry| scope.spawn(|| ping(registry)))
+      .collect();
+    registries
+      .iter()
+      .cloned()
+      .zip(pings)
+      .map(|(registry, ping)| {
+        let health = ping
+          .join()
+          .unwrap_or_else(|_| Err(anyhow::anyhow!("Failed to ping {}", registry.name)));
+        (registry, health)
+      })
+      .collect()
+  })
+}
+
+pub fn exec(meta: &DvmMeta, current_binary: &str) -> Result<()> {
+  let results = ping_all(meta);
+  let width = results.iter().map(|(it, _)| it.name.len()).max().unwrap_or_default();
+  for (registry, health) in &results {
+    let marker = if registry.binary == current_binary { "*" } else { " " };
+    let status = match health {
+      Ok(health) if health.is_consistent() => format!(
+        "{:>6}ms  {}",
+        health.latency.as_millis(),
+        format!("healthy, latest v{}", health.latest).bright_green()
+      ),
+      Ok(health) => format!(
+        "{:>6}ms  {}",
+        health.latency.as_millis(),
+        format!(
+          "{} says v{}, the version list v{}",
+          REGISTRY_LATEST_RELEASE_PATH, health.latest, health.listed
+        )
+        .yellow()
+      ),
+      Err(err) => format!("{:>8}  {}", "-", err.to_string().bright_red()),
+    };
+    println!("{} {:width$}  {}", marker, registry.name, status, width = width);
+  }
+  println!(
+    "Use {} to switch to the fastest healthy registry.",
+    "dvm registry set auto".bright_green()
+  );
+  Ok(())
+}
+
+/// the healthy registry that answered first
+pub fn fastest(meta: &DvmMeta) -> Result<Registry> {
+  ping_all(meta)
+    .into_iter()
+    .filter_map(|(registry, health)| match health {
+      Ok(health) if health.is_consistent() => Some((registry, health.latency)),
+      _ => None,
+    })
+    .min_by_key(|(_, latency)| *latency)
+    .map(|(registry, _)| registry)
+    .ok_or_else(|| anyhow::anyhow!("No registry is healthy, see `dvm registry ping`"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn saved_registries_are_known_after_the_predefined_ones() {
+    let meta = DvmMeta {
+      registries: vec![Registry {
+        name: "corp".to_string(),
+        binary: "https://artifactory.example.com/deno/".to_string(),
+        version: "https://artifactory.example.com/deno/versions.json".to_string(),
+        lts: None,
+        release_template: None,
+        canary_template: None,
+      }],
+      ..Default::default()
+    };
+
+    let names: Vec<String> = known_registries(&meta).into_iter().map(|it| it.name).collect();
+    assert_eq!(names, vec!["official", "cn", "corp"]);
+  }
+}
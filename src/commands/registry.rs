@@ -1,15 +1,21 @@
 use std::process;
 
-use crate::cli::{BinaryRegistryCommands, RegistryCommands, VersionRegistryCommands};
+use crate::cli::{BinaryRegistryCommands, RegistryCommands, RegistryPredefined, VersionRegistryCommands};
 use crate::consts::REGISTRY_NAME_OFFICIAL;
 use crate::consts::REGISTRY_OFFICIAL;
 use crate::consts::{DVM_CONFIGRC_KEY_REGISTRY_BINARY, DVM_CONFIGRC_KEY_REGISTRY_VERSION, REGISTRY_NAME_CN};
-use crate::consts::{REGISTRY_CN, REGISTRY_LIST_CN, REGISTRY_LIST_OFFICIAL};
+use crate::consts::{DVM_CONFIGRC_KEY_REGISTRY_CANARY_TEMPLATE, DVM_CONFIGRC_KEY_REGISTRY_RELEASE_TEMPLATE};
+use crate::consts::{DVM_CONFIGRC_KEY_REGISTRY_LTS, REGISTRY_LTS_OFFICIAL, REGISTRY_NAME_AUTO};
+use crate::consts::{REGISTRY_CN, REGISTRY_LIST_CN, REGISTRY_LIST_OFFICIAL, REGISTRY_VERSIONS_PATH};
+use crate::meta::Registry;
+use crate::oci;
 use crate::DvmMeta;
 
-use crate::configrc::{rc_get_with_fix, rc_update};
-use crate::utils::is_http_like_url;
-use anyhow::Result;
+use crate::configrc::{rc_get, rc_get_with_fix, rc_remove, rc_update};
+use crate::credentials::{redact_url, source_for, Credential, Credentials};
+use crate::http;
+use crate::utils::{is_http_like_url, with_trailing_slash};
+use anyhow::{bail, Result};
 use colored::Colorize;
 
 pub fn exec(meta: &mut DvmMeta, registry: RegistryCommands) -> Result<()> {
@@ -27,48 +33,197 @@ pub fn exec(meta: &mut DvmMeta, registry: RegistryCommands) -> Result<()> {
       println!("{}:", "cn".bright_blue());
       println!("  binary_registry\t{}", REGISTRY_CN);
       println!("  version_registry\t{}", REGISTRY_LIST_CN);
-      println!("Use {} to set the registry.", "dvm registry set <name>".bright_green());
-      println!("for example: {}", "dvm registry set official".bright_green());
+
+      for registry in &meta.registries {
+        println!("{}:", registry.name.bright_blue());
+        println!("  binary_registry\t{}", redact_url(&registry.binary));
+        println!("  version_registry\t{}", redact_url(&registry.version));
+        if let Some(lts) = &registry.lts {
+          println!("  lts_source\t\t{}", redact_url(lts));
+        }
+        if let Some(template) = &registry.release_template {
+          println!("  release_template\t{}", template);
+        }
+        if let Some(template) = &registry.canary_template {
+          println!("  canary_template\t{}", template);
+        }
+      }
+      println!(
+        "Use {} to switch the registry.",
+        "dvm registry use <name>".bright_green()
+      );
+      println!("for example: {}", "dvm registry use official".bright_green());
     }
     RegistryCommands::Show => {
       println! {"{}: ", "current registry info".bright_blue()};
-      println!("  binary_registry\t{}", rc_binary_registry);
-      println!("  version_registry\t{}", rc_version_registry);
-    }
-    RegistryCommands::Official { write_local } => {
-      rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_BINARY, REGISTRY_OFFICIAL)?;
-      rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_VERSION, REGISTRY_LIST_OFFICIAL)?;
-    }
-    RegistryCommands::Cn { write_local } => {
-      rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_BINARY, REGISTRY_CN)?;
-      rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_VERSION, REGISTRY_LIST_CN)?;
+      println!("  binary_registry\t{}", redact_url(&rc_binary_registry));
+      println!("  version_registry\t{}", redact_url(&rc_version_registry));
+      if let Ok(lts) = rc_get(DVM_CONFIGRC_KEY_REGISTRY_LTS) {
+        println!("  lts_source\t\t{}", redact_url(&lts));
+      }
+      if let Ok(template) = rc_get(DVM_CONFIGRC_KEY_REGISTRY_RELEASE_TEMPLATE) {
+        println!("  release_template\t{}", template);
+      }
+      if let Ok(template) = rc_get(DVM_CONFIGRC_KEY_REGISTRY_CANARY_TEMPLATE) {
+        println!("  canary_template\t{}", template);
+      }
+      print_credentials("binary_credentials", &rc_binary_registry)?;
+      print_credentials("version_credentials", &rc_version_registry)?;
     }
+    RegistryCommands::Official { write_local } => switch_registry(write_local, &Registry::official())?,
+    RegistryCommands::Cn { write_local } => switch_registry(write_local, &Registry::cn())?,
+    RegistryCommands::Ping => super::ping::exec(meta, &rc_binary_registry)?,
     RegistryCommands::Set {
       predefined,
       write_local,
+    } => match predefined {
+      RegistryPredefined::Official => switch_registry(write_local, &Registry::official())?,
+      RegistryPredefined::CN => switch_registry(write_local, &Registry::cn())?,
+      RegistryPredefined::Auto => use_fastest(meta, write_local)?,
+    },
+    RegistryCommands::Add {
+      name,
+      binary,
+      version,
+      lts,
+      release_template,
+      canary_template,
+      token_env,
+      token,
+      username,
+      password,
     } => {
-      rc_update(
-        write_local,
-        DVM_CONFIGRC_KEY_REGISTRY_BINARY,
-        &predefined.get_binary_url(),
-      )?;
-      rc_update(
-        write_local,
-        DVM_CONFIGRC_KEY_REGISTRY_VERSION,
-        &predefined.get_version_url(),
-      )?;
+      if !is_valid_name(&name) {
+        bail!(
+          "Invalid registry name {}, use letters, digits, `.`, `_` or `-`, and none of `{}`, `{}` or `{}`",
+          name,
+          REGISTRY_NAME_OFFICIAL,
+          REGISTRY_NAME_CN,
+          REGISTRY_NAME_AUTO
+        );
+      }
+      let Some(binary) = mirror_list(&binary, binary_mirror) else {
+        bail!("Invalid binary registry {}", binary);
+      };
+      let version = match version {
+        Some(version) => match mirror_list(&version, version_mirror) {
+          Some(version) => version,
+          None => bail!("Invalid version registry {}", version),
+        },
+        // the tags of an OCI repository are its version list
+        None if oci::is_oci(&binary) => binary.clone(),
+        None => http::join(&binary, REGISTRY_VERSIONS_PATH),
+      };
+
+      if token_env.is_some() || token.is_some() || username.is_some() {
+        let mut credentials = Credentials::load()?;
+        for url in registry_urls(&binary, &version) {
+          credentials.set(Credential {
+            url,
+            token_env: token_env.clone(),
+            token: token.clone(),
+            username: username.clone(),
+            password: password.clone(),
+          });
+        }
+        credentials.save()?;
+      }
+
+      meta.set_registry(Registry {
+        name: name.clone(),
+        binary,
+        version,
+        lts,
+        release_template,
+        canary_template,
+      });
+      println!(
+        "Registry {} saved, switch to it with {}",
+        name,
+        format!("dvm registry use {}", name).bright_green()
+      );
+    }
+    RegistryCommands::Remove { name } => {
+      let Some(registry) = meta.delete_registry(&name) else {
+        bail!("No registry named {}, see `dvm registry list`", name);
+      };
+      println!("Registry {} removed", name);
+      // the same urls may have been given to `dvm registry login`, so the credentials stay
+      let credentials = Credentials::load()?;
+      for url in registry_urls(&registry.binary, &registry.version) {
+        if credentials.registries.iter().any(|it| it.url == url) {
+          println!(
+            "Credentials for {} are kept, forget them with {}",
+            redact_url(&url),
+            format!("dvm registry logout {}", url).bright_green()
+          );
+        }
+      }
+    }
+    RegistryCommands::Use { name, write_local } => match name.as_str() {
+      REGISTRY_NAME_OFFICIAL => switch_registry(write_local, &Registry::official())?,
+      REGISTRY_NAME_CN => switch_registry(write_local, &Registry::cn())?,
+      REGISTRY_NAME_AUTO => use_fastest(meta, write_local)?,
+      _ => {
+        let Some(registry) = meta.get_registry(&name) else {
+          bail!("No registry named {}, see `dvm registry list`", name);
+        };
+        switch_registry(write_local, registry)?;
+        println!("Now using the registry {}", name);
+      }
+    },
+    RegistryCommands::Login {
+      url,
+      token_env,
+      token,
+      username,
+      password,
+    } => {
+      if !is_http_like_url(&url) && !oci::is_oci(&url) {
+        eprintln!(
+          "The {} is not valid URL, please starts with `http`, `https` or `oci`",
+          url
+        );
+        process::exit(1)
+      }
+      let mut credentials = Credentials::load()?;
+      credentials.set(Credential {
+        url: url.clone(),
+        token_env,
+        token,
+        username,
+        password,
+      });
+      credentials.save()?;
+      println!("Credentials for {} saved to {}", url, Credentials::path().display());
+    }
+    RegistryCommands::Logout { url } => {
+      let mut credentials = Credentials::load()?;
+      if credentials.remove(&url) {
+        credentials.save()?;
+        println!("Credentials for {} removed", url);
+      } else {
+        println!("No credentials stored for {}", url);
+      }
     }
+    RegistryCommands::Serve {
+      port,
+      bind,
+      upstream,
+      upstream_versions,
+      dir,
+    } => super::serve::exec(port, &bind, upstream, upstream_versions, dir)?,
     RegistryCommands::Binary { sub } => match sub {
       BinaryRegistryCommands::Show => {
-        println!("{}: {}", "current binary registry".bright_blue(), rc_binary_registry);
+        println!(
+          "{}: {}",
+          "current binary registry".bright_blue(),
+          redact_url(&rc_binary_registry)
+        );
       }
       BinaryRegistryCommands::Set { custom, write_local } => {
-        if custom == REGISTRY_NAME_OFFICIAL {
-          rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_BINARY, REGISTRY_OFFICIAL)?;
-        } else if custom == REGISTRY_NAME_CN {
-          rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_BINARY, REGISTRY_CN)?;
-        } else if is_http_like_url(&custom) {
-          rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_BINARY, &custom)?;
+        if let Some(registry) = mirror_list(&custom, binary_mirror) {
+          rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_BINARY, &registry)?;
         } else {
           println!("{}: {}", "invalid registry".bright_red(), custom);
           process::exit(1);
@@ -77,17 +232,20 @@ pub fn exec(meta: &mut DvmMeta, registry: RegistryCommands) -> Result<()> {
     },
     RegistryCommands::Version { sub } => match sub {
       VersionRegistryCommands::Show => {
-        println!("{}: {}", "current version registry".bright_blue(), rc_version_registry);
+        println!(
+          "{}: {}",
+          "current version registry".bright_blue(),
+          redact_url(&rc_version_registry)
+        );
       }
       VersionRegistryCommands::Set { custom, write_local } => {
-        if custom == REGISTRY_NAME_OFFICIAL {
-          rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_VERSION, REGISTRY_LIST_OFFICIAL)?;
-        } else if custom == REGISTRY_NAME_CN {
-          rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_VERSION, REGISTRY_LIST_CN)?;
-        } else if is_http_like_url(&custom) {
-          rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_VERSION, &custom)?;
+        if let Some(registry) = mirror_list(&custom, version_mirror) {
+          rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_VERSION, &registry)?;
         } else {
-          eprintln!("The {} is not valid URL, please starts with `http` or `https`", custom);
+          eprintln!(
+            "The {} is not valid URL, please starts with `http`, `https` or `file`, or give an existing versions.json",
+            custom
+          );
           eprintln!("Registry will not be changed");
           process::exit(1)
         }
@@ -98,3 +256,153 @@ pub fn exec(meta: &mut DvmMeta, registry: RegistryCommands) -> Result<()> {
   meta.save();
   Ok(())
 }
+
+/// point the `.dvmrc` at a registry, settings the registry does not have are removed to use their defaults
+fn switch_registry(write_local: bool, registry: &Registry) -> Result<()> {
+  rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_BINARY, &registry.binary)?;
+  rc_update(write_local, DVM_CONFIGRC_KEY_REGISTRY_VERSION, &registry.version)?;
+  let optional = [
+    (
+      DVM_CONFIGRC_KEY_REGISTRY_LTS,
+      registry.lts.as_deref().filter(|it| *it != REGISTRY_LTS_OFFICIAL),
+    ),
+    (
+      DVM_CONFIGRC_KEY_REGISTRY_RELEASE_TEMPLATE,
+      registry.release_template.as_deref(),
+    ),
+    (
+      DVM_CONFIGRC_KEY_REGISTRY_CANARY_TEMPLATE,
+      registry.canary_template.as_deref(),
+    ),
+  ];
+  for (key, value) in optional {
+    match value {
+      Some(value) => rc_update(write_local, key, value)?,
+      None => rc_remove(write_local, key)?,
+    }
+  }
+  Ok(())
+}
+
+/// switch to the fastest healthy of the known registries
+fn use_fastest(meta: &DvmMeta, write_local: bool) -> Result<()> {
+  let registry = super::ping::fastest(meta)?;
+  switch_registry(write_local, &registry)?;
+  println!("Now using the fastest healthy registry {}", registry.name);
+  Ok(())
+}
+
+/// a saved registry cannot shadow a predefined one
+fn is_valid_name(name: &str) -> bool {
+  !name.is_empty()
+    && ![REGISTRY_NAME_OFFICIAL, REGISTRY_NAME_CN, REGISTRY_NAME_AUTO].contains(&name)
+    && name
+      .chars()
+      .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '_' | '-'))
+}
+
+/// the http mirrors of a binary and a version registry, the urls credentials are stored for
+fn registry_urls(binary: &str, version: &str) -> Vec<String> {
+  let mut urls: Vec<String> = Vec::new();
+  for url in http::mirrors(binary).into_iter().chain(http::mirrors(version)) {
+    if (is_http_like_url(url) || oci::is_oci(url)) && !urls.iter().any(|it| url.starts_with(it.as_str())) {
+      urls.push(url.to_string());
+    }
+  }
+  urls
+}
+
+/// print where the credentials of every mirror of a registry come from
+fn print_credentials(label: &str, registry: &str) -> Result<()> {
+  let mirrors = http::mirrors(registry);
+  for mirror in &mirrors {
+    if let Some(source) = source_for(mirror)? {
+      if mirrors.len() > 1 {
+        println!("  {}\t{} for {}", label, source.describe(), redact_url(mirror));
+      } else {
+        println!("  {}\t{}", label, source.describe());
+      }
+    }
+  }
+  Ok(())
+}
+
+/// a registry or a comma-separated list of mirrors tried in order, `None` if any of them is invalid
+fn mirror_list(custom: &str, mirror: impl Fn(&str) -> Option<String>) -> Option<String> {
+  let mirrors = http::mirrors(custom)
+    .into_iter()
+    .map(mirror)
+    .collect::<Option<Vec<_>>>()?;
+  (!mirrors.is_empty()).then(|| mirrors.join(","))
+}
+
+/// a predefined registry, an http url, an OCI repository or a local registry
+fn binary_mirror(custom: &str) -> Option<String> {
+  match custom {
+    REGISTRY_NAME_OFFICIAL => Some(REGISTRY_OFFICIAL.to_string()),
+    REGISTRY_NAME_CN => Some(REGISTRY_CN.to_string()),
+    _ if is_http_like_url(custom) => Some(with_trailing_slash(custom)),
+    _ if oci::is_oci(custom) => Some(custom.trim_end_matches('/').to_string()),
+    _ => local_binary_registry(custom),
+  }
+}
+
+/// the version list of a predefined registry, an http url, the tags of an OCI repository or a local version list
+fn version_mirror(custom: &str) -> Option<String> {
+  match custom {
+    REGISTRY_NAME_OFFICIAL => Some(REGISTRY_LIST_OFFICIAL.to_string()),
+    REGISTRY_NAME_CN => Some(REGISTRY_LIST_CN.to_string()),
+    _ if is_http_like_url(custom) => Some(custom.to_string()),
+    _ if oci::is_oci(custom) => Some(custom.trim_end_matches('/').to_string()),
+    _ => local_version_registry(custom),
+  }
+}
+
+/// a `file://` url or an existing directory to use as the binary registry,
+/// a path is made absolute so the registry works from any directory
+fn local_binary_registry(custom: &str) -> Option<String> {
+  if custom.starts_with("file://") {
+    return Some(with_trailing_slash(custom));
+  }
+  let path = std::path::absolute(custom).ok()?;
+  path.is_dir().then(|| with_trailing_slash(&path.to_string_lossy()))
+}
+
+/// a `file://` url or an existing `versions.json`, or the directory it is in, to use as the version registry
+fn local_version_registry(custom: &str) -> Option<String> {
+  if custom.starts_with("file://") {
+    return Some(custom.to_string());
+  }
+  let mut path = std::path::absolute(custom).ok()?;
+  if path.is_dir() {
+    path.push(REGISTRY_VERSIONS_PATH);
+  }
+  path.is_file().then(|| path.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn saved_registries_cannot_shadow_predefined_ones() {
+    assert!(is_valid_name("corp"));
+    assert!(is_valid_name("corp-eu.2"));
+    assert!(!is_valid_name(""));
+    assert!(!is_valid_name("official"));
+    assert!(!is_valid_name("cn"));
+    assert!(!is_valid_name("auto"));
+    assert!(!is_valid_name("corp registry"));
+  }
+
+  #[test]
+  fn credentials_cover_every_mirror_once() {
+    assert_eq!(
+      registry_urls(
+        "https://a.example.com/deno/,/mnt/mirror/",
+        "https://a.example.com/deno/versions.json,https://b.example.com/versions.json"
+      ),
+      vec!["https://a.example.com/deno/", "https://b.example.com/versions.json"]
+    );
+  }
+}
@@ -0,0 +1,134 @@
+use crate::consts::{DVM_VERSION_CANARY, DVM_VERSION_SYSTEM};
+use crate::meta::DvmMeta;
+use crate::resolve::{resolve, resolve_default, Resolution, Resolved, Source};
+use anyhow::Result;
+use colored::Colorize;
+use semver::Version;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// what `dvm resolve --json` prints
+#[derive(Serialize)]
+struct Output<'a> {
+  /// the version argument, none when the `.dvmrc` file or the default was used
+  input: Option<&'a str>,
+  kind: &'static str,
+  version: Option<&'a Version>,
+  toolchain: Option<&'a str>,
+  binary: Option<PathBuf>,
+  installed: bool,
+  source: &'a Source,
+}
+
+pub fn exec(meta: &DvmMeta, version: Option<String>, explain: bool, json: bool) -> Result<()> {
+  let resolution = match &version {
+    Some(version) => resolve(meta, version)?,
+    None => resolve_default(meta)?,
+  };
+  let binary = resolution.binary();
+  let installed = binary.as_ref().is_some_and(|it| it.exists());
+
+  if json {
+    let output = output(&resolution, version.as_deref(), binary, installed);
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    return Ok(());
+  }
+
+  if !explain {
+    println!("{}", answer(&resolution.resolved));
+    return Ok(());
+  }
+
+  for line in resolution.explain() {
+    println!("{}", line);
+  }
+  println!(
+    "Resolved {} to {}",
+    resolution.input,
+    answer(&resolution.resolved).bright_green()
+  );
+  match binary {
+    Some(binary) if installed => println!("Binary: {}", binary.display()),
+    Some(binary) => println!("Binary: {} {}", binary.display(), "(not installed)".yellow()),
+    None => println!("Binary: {}", "not found".yellow()),
+  }
+  Ok(())
+}
+
+fn answer(resolved: &Resolved) -> String {
+  match resolved {
+    Resolved::Version(version) => version.to_string(),
+    Resolved::Toolchain { name, .. } => name.clone(),
+    Resolved::Canary => DVM_VERSION_CANARY.to_string(),
+    Resolved::System => DVM_VERSION_SYSTEM.to_string(),
+  }
+}
+
+fn output<'a>(
+  resolution: &'a Resolution,
+  input: Option<&'a str>,
+  binary: Option<PathBuf>,
+  installed: bool,
+) -> Output<'a> {
+  let (kind, version, toolchain) = match &resolution.resolved {
+    Resolved::Version(version) => ("version", Some(version), None),
+    Resolved::Toolchain { name, .. } => ("toolchain", None, Some(name.as_str())),
+    Resolved::Canary => ("canary", None, None),
+    Resolved::System => ("system", None, None),
+  };
+  Output {
+    input,
+    kind,
+    version,
+    toolchain,
+    binary,
+    installed,
+    source: &resolution.source,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::resolve::Candidates;
+  use semver::VersionReq;
+
+  #[test]
+  fn json_output_nests_the_source_chain() {
+    let resolution = Resolution {
+      input: "stable".to_string(),
+      resolved: Resolved::Version(Version::parse("1.40.2").unwrap()),
+      source: Source::Alias {
+        name: "stable".to_string(),
+        target: "~1.40".to_string(),
+        source: Box::new(Source::Range {
+          range: VersionReq::parse("~1.40").unwrap(),
+          candidates: Candidates::Local { count: 3 },
+        }),
+      },
+    };
+    let output = output(&resolution, Some("stable"), None, false);
+
+    assert_eq!(
+      serde_json::to_value(&output).unwrap(),
+      serde_json::json!({
+        "input": "stable",
+        "kind": "version",
+        "version": "1.40.2",
+        "toolchain": null,
+        "binary": null,
+        "installed": false,
+        "source": {
+          "kind": "alias",
+          "name": "stable",
+          "target": "~1.40",
+          "source": {
+            "kind": "range",
+            "range": "~1.40",
+            "candidates": { "kind": "local", "count": 3 }
+          }
+        }
+      })
+    );
+  }
+}
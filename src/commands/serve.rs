@@ -0,0 +1,315 @@
+use super::install::{canary_template, expand_template, fetch_checksum, release_template, verify_checksum};
+use crate::archives::checksum_path;
+use crate::configrc::rc_get_with_fix;
+use crate::consts::{
+  DVM_CONFIGRC_KEY_REGISTRY_BINARY, DVM_CONFIGRC_KEY_REGISTRY_VERSION, REGISTRY_CANARY_TEMPLATE_DEFAULT, REGISTRY_CN,
+  REGISTRY_LATEST_CANARY_PATH, REGISTRY_LATEST_RELEASE_PATH, REGISTRY_LIST_CN, REGISTRY_LIST_OFFICIAL,
+  REGISTRY_NAME_CN, REGISTRY_NAME_OFFICIAL, REGISTRY_OFFICIAL, REGISTRY_RELEASE_TEMPLATE_DEFAULT,
+  REGISTRY_SERVE_WORKERS, REGISTRY_VERSIONS_PATH,
+};
+use crate::credentials::redact_url;
+use crate::http;
+use crate::oci;
+use crate::utils::{dvm_registry_cache, is_semver, with_trailing_slash, write_atomic, Reporter};
+use anyhow::Result;
+use colored::Colorize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tiny_http::{Header, Method, Request, Response, ResponseBox, Server};
+
+/// The registry files are fetched from.
+struct Upstream {
+  binary: String,
+  versions: String,
+  release_template: String,
+  canary_template: String,
+}
+
+/// A file of the registry layout, nothing else is served.
+#[derive(Debug, Eq, PartialEq)]
+enum RegistryFile {
+  /// `versions.json` and the `*-latest.txt` files, which change with every release
+  Index(String),
+  /// archives and their checksums, which never change once released
+  Release(String),
+}
+
+pub fn exec(
+  port: u16,
+  bind: &str,
+  upstream: Option<String>,
+  upstream_versions: Option<String>,
+  dir: Option<PathBuf>,
+) -> Result<()> {
+  let upstream = resolve_upstream(upstream, upstream_versions)?;
+  let dir = dir.unwrap_or_else(dvm_registry_cache);
+  fs::create_dir_all(&dir)?;
+
+  let server =
+    Server::http((bind, port)).map_err(|err| anyhow::anyhow!("Failed to listen on {}:{}: {}", bind, port, err))?;
+  println!(
+    "Serving {} on http://{}:{}/, fetching what is not cached from {}",
+    dir.display(),
+    bind,
+    port,
+    redact_url(&upstream.binary)
+  );
+  println!(
+    "Point other dvm clients at it with {} and {}",
+    format!("dvm registry binary set http://<host>:{}/", port).bright_green(),
+    format!("dvm registry version set http://<host>:{}/versions.json", port).bright_green()
+  );
+
+  // a fixed number of workers take turns accepting requests, the rest queue up in the server
+  std::thread::scope(|scope| {
+    for _ in 0..REGISTRY_SERVE_WORKERS {
+      scope.spawn(|| {
+        for request in server.incoming_requests() {
+          handle(request, &upstream, &dir);
+        }
+      });
+    }
+  });
+  Ok(())
+}
+
+/// `official`, `cn`, the url of a binary registry, or the registries of the `.dvmrc`
+fn resolve_upstream(upstream: Option<String>, upstream_versions: Option<String>) -> Result<Upstream> {
+  let (release_template, canary_template) = match upstream {
+    None => (release_template(), canary_template()),
+    Some(_) => (
+      REGISTRY_RELEASE_TEMPLATE_DEFAULT.to_string(),
+      REGISTRY_CANARY_TEMPLATE_DEFAULT.to_string(),
+    ),
+  };
+  let (binary, versions) = match upstream.as_deref() {
+    None => (
+      rc_get_with_fix(DVM_CONFIGRC_KEY_REGISTRY_BINARY)?,
+      rc_get_with_fix(DVM_CONFIGRC_KEY_REGISTRY_VERSION)?,
+    ),
+    Some(REGISTRY_NAME_OFFICIAL) => (REGISTRY_OFFICIAL.to_string(), REGISTRY_LIST_OFFICIAL.to_string()),
+    Some(REGISTRY_NAME_CN) => (REGISTRY_CN.to_string(), REGISTRY_LIST_CN.to_string()),
+    Some(url) => {
+      let binary = http::mirrors(url)
+        .into_iter()
+        .map(with_trailing_slash)
+        .collect::<Vec<_>>()
+        .join(",");
+      let versions = http::join(&binary, REGISTRY_VERSIONS_PATH);
+      (binary, versions)
+    }
+  };
+  if oci::is_oci(&binary) {
+    anyhow::bail!("Serving the OCI registry {} is not supported", binary);
+  }
+  Ok(Upstream {
+    binary,
+    versions: upstream_versions.unwrap_or(versions),
+    release_template,
+    canary_template,
+  })
+}
+
+fn handle(request: Request, upstream: &Upstream, dir: &Path) {
+  let path = request
+    .url()
+    .split(['?', '#'])
+    .next()
+    .unwrap_or_default()
+    .trim_start_matches('/')
+    .to_string();
+  let file = match request.method() {
+    Method::Get | Method::Head => registry_file(&path),
+    _ => None,
+  };
+
+  let (response, origin): (ResponseBox, _) = match file.map(|it| fetch(&it, upstream, dir)) {
+    Some(Ok(Some((path_on_disk, origin)))) => match fs::File::open(&path_on_disk) {
+      Ok(file) => {
+        let content_type = if path.ends_with(".json") {
+          "application/json"
+        } else if path.ends_with(".txt") || path.ends_with(".sha256sum") {
+          "text/plain; charset=utf-8"
+        } else {
+          "application/octet-stream"
+        };
+        let header = Header::from_bytes("Content-Type", content_type).unwrap();
+        // archives are streamed from the disk rather than read into memory
+        (Response::from_file(file).with_header(header).boxed(), origin)
+      }
+      Err(err) => {
+        eprintln!("{} {}: {}", "error:".red(), path_on_disk.display(), err);
+        (Response::empty(500).boxed(), "")
+      }
+    },
+    Some(Ok(None)) | None => (Response::empty(404).boxed(), ""),
+    Some(Err(err)) => {
+      eprintln!("{} {}", "error:".red(), err);
+      (Response::empty(502).boxed(), "")
+    }
+  };
+  println!(
+    "{} /{} {} {}",
+    request.method(),
+    path,
+    response.status_code().0,
+    origin.bright_black()
+  );
+  let _ = request.respond(response);
+}
+
+/// the registry file a request path points to
+fn registry_file(path: &str) -> Option<RegistryFile> {
+  if [
+    REGISTRY_VERSIONS_PATH,
+    REGISTRY_LATEST_RELEASE_PATH,
+    REGISTRY_LATEST_CANARY_PATH,
+  ]
+  .contains(&path)
+  {
+    return Some(RegistryFile::Index(path.to_string()));
+  }
+
+  let is_file_name = |it: &str| {
+    !it.is_empty()
+      && !it.starts_with('.')
+      && it
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '.' | '_' | '-'))
+  };
+  let is_hash = |it: &str| !it.is_empty() && it.chars().all(|ch| ch.is_ascii_hexdigit());
+  match path.split('/').collect::<Vec<_>>().as_slice() {
+    ["release", version, name] if version.strip_prefix('v').is_some_and(is_semver) && is_file_name(name) => {
+      Some(RegistryFile::Release(path.to_string()))
+    }
+    ["canary", hash, name] if is_hash(hash) && is_file_name(name) => Some(RegistryFile::Release(path.to_string())),
+    _ => None,
+  }
+}
+
+/// the registry file in `dir`, fetched from the upstream unless cached, and where it came from.
+/// None if the upstream does not have it.
+fn fetch(file: &RegistryFile, upstream: &Upstream, dir: &Path) -> Result<Option<(PathBuf, &'static str)>> {
+  match file {
+    RegistryFile::Index(name) => {
+      let url = if name == REGISTRY_VERSIONS_PATH {
+        upstream.versions.clone()
+      } else {
+        http::join(&upstream.binary, name)
+      };
+      let cached = dir.join(name);
+      match fetch_upstream(&url) {
+        Ok(Some(data)) => {
+          write_atomic(&cached, &data)?;
+          Ok(Some((cached, "upstream")))
+        }
+        // the last known index keeps the clients working while the upstream is unreachable
+        result if cached.is_file() => {
+          if let Err(err) = result {
+            eprintln!("{}, serving the cached {}", err, name);
+          }
+          Ok(Some((cached, "cache")))
+        }
+        result => result.map(|_| None),
+      }
+    }
+    RegistryFile::Release(path) => {
+      let cached = dir.join(path);
+      if cached.is_file() {
+        return Ok(Some((cached, "cache")));
+      }
+
+      let url = upstream_url(upstream, path);
+      let Some(data) = fetch_upstream(&url)? else {
+        return Ok(None);
+      };
+      fs::create_dir_all(cached.parent().unwrap())?;
+      if !path.ends_with(".sha256sum") {
+        // the digest is only kept for the clients to verify against when the upstream published it,
+        // otherwise the clients get the same 404 for the checksum as from the upstream
+        let expected = fetch_checksum(&url)?;
+        if let Some(digest) = verify_checksum(&url, &data[..], expected.as_deref(), &Reporter::default())? {
+          let name = cached.file_name().unwrap_or_default().to_string_lossy().to_string();
+          write_atomic(&checksum_path(&cached), format!("{}  {}\n", digest, name))?;
+        }
+      }
+      write_atomic(&cached, &data)?;
+      Ok(Some((cached, "upstream")))
+    }
+  }
+}
+
+/// the upstream url of a file in the registry layout, laid out by the templates of the upstream
+fn upstream_url(upstream: &Upstream, path: &str) -> String {
+  let (path, checksum) = match path.strip_suffix(".sha256sum") {
+    Some(archive) => (archive, true),
+    None => (path, false),
+  };
+  let url = match path.split('/').collect::<Vec<_>>().as_slice() {
+    ["release", version, name] => expand_template(&upstream.release_template, version.trim_start_matches('v'), name),
+    ["canary", hash, name] => expand_template(&upstream.canary_template, hash, name),
+    _ => path.to_string(),
+  };
+  let url = http::join(&upstream.binary, &url);
+  if checksum {
+    http::join(&url, ".sha256sum")
+  } else {
+    url
+  }
+}
+
+fn fetch_upstream(url: &str) -> Result<Option<Vec<u8>>> {
+  let response = http::get(url)?;
+  match response.status() {
+    404 => Ok(None),
+    status if status >= 400 => anyhow::bail!("Request to '{}' failed: {}", url, status),
+    _ => Ok(Some(response.into_bytes()?)),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn only_registry_files_are_served() {
+    let release = |it: &str| Some(RegistryFile::Release(it.to_string()));
+
+    assert_eq!(
+      registry_file("versions.json"),
+      Some(RegistryFile::Index("versions.json".to_string()))
+    );
+    assert_eq!(
+      registry_file("release/v2.1.4/deno-x86_64-unknown-linux-gnu.zip"),
+      release("release/v2.1.4/deno-x86_64-unknown-linux-gnu.zip")
+    );
+    assert_eq!(
+      registry_file("canary/3f2a9c1/deno-x86_64-unknown-linux-gnu.zip.sha256sum"),
+      release("canary/3f2a9c1/deno-x86_64-unknown-linux-gnu.zip.sha256sum")
+    );
+    assert_eq!(registry_file("release/v2.1.4/../../credentials.json"), None);
+    assert_eq!(registry_file("release/v2.1.4/.."), None);
+    assert_eq!(registry_file("release/latest/deno.zip"), None);
+    assert_eq!(registry_file("canary/main/deno.zip"), None);
+    assert_eq!(registry_file("credentials.json"), None);
+  }
+
+  #[test]
+  fn upstream_urls_follow_the_upstream_layout() {
+    let upstream = Upstream {
+      binary: "https://github.com/denoland/deno/".to_string(),
+      versions: "https://deno.com/versions.json".to_string(),
+      release_template: "releases/download/v{version}/{archive}".to_string(),
+      canary_template: REGISTRY_CANARY_TEMPLATE_DEFAULT.to_string(),
+    };
+
+    assert_eq!(
+      upstream_url(&upstream, "release/v2.1.4/deno-x86_64-unknown-linux-gnu.zip.sha256sum"),
+      "https://github.com/denoland/deno/releases/download/v2.1.4/deno-x86_64-unknown-linux-gnu.zip.sha256sum"
+    );
+    assert_eq!(
+      upstream_url(&upstream, "canary/3f2a9c1/deno-x86_64-unknown-linux-gnu.zip"),
+      "https://github.com/denoland/deno/canary/3f2a9c1/deno-x86_64-unknown-linux-gnu.zip"
+    );
+  }
+}
@@ -1,21 +1,45 @@
 use crate::consts::DVM_CACHE_PATH_PREFIX;
-use crate::utils::{deno_version_path, dvm_root};
+use crate::meta::DvmMeta;
+use crate::resolve::{resolve_installed, Resolved};
+use crate::utils::{deno_bin_path, deno_version_path, dvm_root};
 use crate::version::current_version;
 use anyhow::Result;
-use semver::Version;
 use std::fs;
 use std::process::exit;
 
-pub fn exec(version: Option<String>) -> Result<()> {
-  let target_version = match version {
-    Some(target_version) => match Version::parse(&target_version) {
-      Ok(ver) => ver,
-      Err(_) => {
-        eprintln!("Invalid semver");
-        exit(1)
+pub fn exec(meta: &mut DvmMeta, version: Option<String>) -> Result<()> {
+  let resolution = match version {
+    Some(version) => resolve_installed(meta, &version)?,
+    None => anyhow::bail!("Please specify a version to uninstall"),
+  };
+  for line in resolution.explain() {
+    println!("{}", line);
+  }
+
+  let target_version = match resolution.resolved {
+    Resolved::Toolchain { name, path } => {
+      // `deno` is a symlink to the toolchain while it is in use
+      if fs::read_link(deno_bin_path()).is_ok_and(|it| it == path) {
+        println!("Failed: toolchain {} is in use.", name);
+        exit(1);
       }
-    },
-    None => unimplemented!(),
+      meta.delete_toolchain(&name);
+      let stale: Vec<String> = meta
+        .versions
+        .iter()
+        .filter(|it| it.required == name || it.current == name)
+        .map(|it| it.required.clone())
+        .collect();
+      for required in stale {
+        meta.delete_version_mapping(required);
+      }
+      println!("toolchain {} unlinked, the executable is left in place.", name);
+      return Ok(());
+    }
+    Resolved::Version(version) => version,
+    Resolved::Canary | Resolved::System => {
+      anyhow::bail!("`{}` can not be uninstalled, give a version", resolution.input)
+    }
   };
   let target_exe_path = deno_version_path(&target_version);
 
@@ -26,16 +50,15 @@ pub fn exec(version: Option<String>) -> Result<()> {
     exit(1)
   }
 
-  let current_version = current_version().unwrap();
-
-  if current_version == target_version.to_string() {
+  // without a deno on `PATH` no version is in use
+  if current_version() == Some(target_version.to_string()) {
     println!("Failed: deno v{} is in use.", target_version);
     exit(1);
   }
 
   let version_dir = dvm_root().join(format!("{}/{}", DVM_CACHE_PATH_PREFIX, target_version));
 
-  fs::remove_dir_all(version_dir).unwrap();
+  fs::remove_dir_all(version_dir)?;
   println!("deno v{} removed.", target_version);
 
   Ok(())
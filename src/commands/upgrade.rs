@@ -1,17 +1,16 @@
 use crate::{
   commands::install,
   consts::{DVM_VERSION_CANARY, DVM_VERSION_INVALID, DVM_VERSION_SELF},
-  utils::best_version,
-  version::{get_latest_lts_version, remote_versions, VersionArg},
+  http,
+  resolve::{resolve, Resolved},
+  utils::deno_canary_path,
   DvmMeta,
 };
 use anyhow::{Ok, Result};
 use colored::Colorize;
 use std::fs;
-use std::str::FromStr;
 
 pub fn exec(meta: &mut DvmMeta, alias: Option<String>) -> Result<()> {
-  let versions = remote_versions().expect("Fetching version list failed.");
   if let Some(alias) = alias {
     if alias == DVM_VERSION_SELF {
       upgrade_self()?;
@@ -20,7 +19,7 @@ pub fn exec(meta: &mut DvmMeta, alias: Option<String>) -> Result<()> {
 
     if alias == DVM_VERSION_CANARY {
       println!("Upgrading {}", alias.bright_black());
-      install::exec(meta, true, Some(alias)).unwrap();
+      install::exec(meta, true, Some(alias))?;
       println!("All aliases have been upgraded");
       return Ok(());
     }
@@ -36,25 +35,20 @@ pub fn exec(meta: &mut DvmMeta, alias: Option<String>) -> Result<()> {
     let current = meta
       .get_version_mapping(alias.as_str())
       .unwrap_or_else(|| DVM_VERSION_INVALID.to_string());
-    let version_req = meta.resolve_version_req(&alias);
-    match version_req {
-      VersionArg::Exact(v) => {
-        if current == v.to_string() {
+    match resolve(meta, &alias)?.resolved {
+      Resolved::Version(version) => {
+        if current == version.to_string() {
           println!("{} is already the latest version", alias);
           std::process::exit(0);
-        } else {
-          install::exec(meta, true, Some(v.to_string())).expect("Install failed");
         }
-      }
-      VersionArg::Lts => {
-        let version = get_latest_lts_version()?;
-        install::exec(meta, true, Some(version.to_string())).expect("Install failed");
+        install::exec(meta, true, Some(version.to_string()))?;
         meta.set_version_mapping(alias, version.to_string());
       }
-      VersionArg::Range(r) => {
-        let version = best_version(versions.iter().map(AsRef::as_ref), r).unwrap();
-        install::exec(meta, true, Some(version.to_string())).expect("Install failed");
-        meta.set_version_mapping(alias, version.to_string());
+      Resolved::Toolchain { name, .. } => {
+        println!("{} points to the linked toolchain {}, nothing to upgrade", alias, name);
+      }
+      Resolved::Canary | Resolved::System => {
+        println!("{} is not pinned to a version, nothing to upgrade", alias);
       }
     }
   } else {
@@ -63,10 +57,10 @@ pub fn exec(meta: &mut DvmMeta, alias: Option<String>) -> Result<()> {
         .get_version_mapping(alias.name.as_str())
         .unwrap_or_else(|| DVM_VERSION_INVALID.to_string());
 
-      let latest = match VersionArg::from_str(alias.required.clone().as_str()).unwrap() {
-        VersionArg::Exact(v) => v.to_string(),
-        VersionArg::Lts => get_latest_lts_version()?.to_string(),
-        VersionArg::Range(v) => best_version(versions.iter().map(AsRef::as_ref), v).unwrap().to_string(),
+      let latest = match resolve(meta, &alias.name)?.resolved {
+        Resolved::Version(version) => version.to_string(),
+        // linked toolchains are rebuilt by their owners, not upgraded
+        _ => continue,
       };
 
       if current == latest {
@@ -81,9 +75,11 @@ pub fn exec(meta: &mut DvmMeta, alias: Option<String>) -> Result<()> {
       );
       install::exec(meta, true, Some(latest.clone()))?;
       meta.set_version_mapping(alias.name, latest);
+    }
 
+    if deno_canary_path().exists() {
       println!("Upgrading {}", DVM_VERSION_CANARY.bright_black());
-      install::exec(meta, true, Some(DVM_VERSION_CANARY.to_string())).unwrap();
+      install::exec(meta, true, Some(DVM_VERSION_CANARY.to_string()))?;
     }
 
     println!("All aliases have been upgraded");
@@ -96,8 +92,7 @@ fn upgrade_self() -> Result<()> {
   cfg_if::cfg_if! {
     if #[cfg(windows)] {
       let url = "https://raw.githubusercontent.com/justjavac/dvm/main/install.ps1";
-      let script = tinyget::get(url).send()?;
-      let script = script.as_str()?;
+      let script = http::get_string(url)?;
       let tmp = tempfile::tempdir()?;
       let tmp = tmp.path().join("install.ps1");
       fs::write(&tmp, script)?;
@@ -109,8 +104,7 @@ fn upgrade_self() -> Result<()> {
       }
     } else {
       let url = "https://raw.githubusercontent.com/justjavac/dvm/main/install.sh";
-      let script = tinyget::get(url).send()?;
-      let script = script.as_str()?;
+      let script = http::get_string(url)?;
       let tmp = tempfile::tempdir()?;
       let tmp = tmp.path().join("install.sh");
       fs::write(&tmp, script)?;
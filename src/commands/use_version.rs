@@ -1,97 +1,69 @@
+use crate::archives;
 use crate::commands::install;
-use crate::configrc::{rc_get_with_fix, rc_update};
-use crate::consts::{
-  DVM_CONFIGRC_KEY_DENO_VERSION, DVM_CONFIGRC_KEY_REGISTRY_VERSION, DVM_VERSION_CANARY, DVM_VERSION_LATEST,
-  DVM_VERSION_LTS, DVM_VERSION_SYSTEM, REGISTRY_LIST_OFFICIAL,
-};
+use crate::configrc::rc_update;
+use crate::consts::{ARCHIVE_NAME, DVM_CONFIGRC_KEY_DENO_VERSION, DVM_VERSION_CANARY, DVM_VERSION_LATEST};
 use crate::deno_bin_path;
-use crate::meta::DvmMeta;
-use crate::utils::{best_version, deno_canary_path, deno_version_path, prompt_request, run_with_spinner, update_stub};
-use crate::utils::{is_exact_version, load_dvmrc};
-use crate::version::remote_versions;
-use crate::version::{get_latest_lts_version, get_latest_remote_version, VersionArg};
+use crate::http;
+use crate::meta::{DvmMeta, Toolchain};
+use crate::resolve::{resolve, resolve_default, Resolved, Source};
+use crate::utils::{deno_canary_path, deno_version_path, prompt_request, run_with_spinner, update_stub};
 use anyhow::Result;
-use semver::{Version, VersionReq};
+use semver::Version;
 use std::fs;
 use std::path::Path;
 use std::process::Command;
 
 /// using a tag or a specific version
 pub fn exec(meta: &mut DvmMeta, version: Option<String>, write_local: bool) -> Result<()> {
-  let rc_version_url =
-    rc_get_with_fix(DVM_CONFIGRC_KEY_REGISTRY_VERSION).unwrap_or_else(|_| REGISTRY_LIST_OFFICIAL.to_string());
+  let resolution = if let Some(ref version) = version {
+    resolve(meta, version)?
+  } else {
+    println!("No version input detect, try to use version in .dvmrc file");
+    resolve_default(meta)?
+  };
+  for line in resolution.explain() {
+    println!("{}", line);
+  }
 
-  let version_req = if let Some(ref version) = version {
-    if version == &DVM_VERSION_CANARY.to_string() {
+  let used_version = match resolution.resolved {
+    Resolved::Canary => {
       let canary_path = deno_canary_path();
       if !canary_path.exists() {
         if prompt_request("deno canary is not installed. do you want to install it?") {
-          install::exec(meta, true, Some(DVM_VERSION_CANARY.to_string())).unwrap();
-          use_canary_bin_path(write_local).unwrap();
+          install::exec(meta, true, Some(DVM_VERSION_CANARY.to_string()))?;
         } else {
           std::process::exit(1);
         }
       }
 
-      use_canary_bin_path(write_local).unwrap();
+      use_canary_bin_path(write_local)?;
       return Ok(());
-    } else if version == &DVM_VERSION_SYSTEM.to_string() {
-      std::fs::remove_file(deno_bin_path()).unwrap();
+    }
+    Resolved::System => {
+      std::fs::remove_file(deno_bin_path())?;
       println!("Deno that was previously installed on your system will be activated now.");
       return Ok(());
-    } else if version == DVM_VERSION_LTS {
-      VersionArg::Lts
-    } else if version == DVM_VERSION_LATEST {
-      VersionArg::Range(VersionReq::parse("*").unwrap())
-    } else if is_exact_version(version) {
-      VersionArg::Exact(Version::parse(version).unwrap())
-    } else if meta.has_alias(version) {
-      meta.resolve_version_req(version)
-    } else {
-      // dvm will reject for using semver range directly now.
-      eprintln!(
-        "`{}` is not a valid semver version or tag and will not be used\ntype `dvm help` for more info",
-        version
-      );
-      std::process::exit(1);
     }
-  } else {
-    println!("No version input detect, try to use version in .dvmrc file");
-    let version_req = load_dvmrc();
-    println!("Using semver range: {}", version_req);
-    version_req
-  };
-
-  let used_version = match &version_req {
-    VersionArg::Lts => {
-      println!("Checking for latest LTS version");
-      let version = get_latest_lts_version()?;
-      println!("The latest LTS version is v{}", version);
-      version
-    }
-    VersionArg::Range(r) if r.to_string() == "*" => {
-      println!("Checking for latest version");
-      let version = get_latest_remote_version(&rc_version_url)?;
-      println!("The latest version is v{}", version);
-      version
-    }
-    VersionArg::Exact(v) => v.clone(),
-    VersionArg::Range(r) => {
-      println!("Fetching version list");
-      let versions = remote_versions().expect("Fetching version list failed.");
-      best_version(versions.iter().map(AsRef::as_ref), r.clone()).unwrap()
+    Resolved::Toolchain { name, .. } => {
+      let toolchain = meta.get_toolchain(&name).unwrap();
+      return use_toolchain(toolchain, version.unwrap_or(name), write_local);
     }
+    Resolved::Version(version) => version,
   };
 
   let new_exe_path = deno_version_path(&used_version);
 
   if !new_exe_path.exists() {
+    if http::is_offline() && !archives::contains(&used_version, ARCHIVE_NAME) {
+      anyhow::bail!(
+        "deno v{} is not installed and cannot be downloaded in offline mode",
+        used_version
+      );
+    }
     if prompt_request(format!("deno v{} is not installed. do you want to install it?", used_version).as_str()) {
-      install::exec(meta, true, Some(used_version.to_string())).unwrap();
-      let temp = version_req.to_string();
-      let version = version.as_ref().unwrap_or(&temp);
-      if !is_exact_version(version) {
-        meta.set_version_mapping(version.clone(), used_version.to_string());
+      install::exec(meta, true, Some(used_version.to_string()))?;
+      if resolution.source != Source::Exact {
+        meta.set_version_mapping(resolution.input.clone(), used_version.to_string());
       }
     } else {
       std::process::exit(1);
@@ -121,7 +93,8 @@ pub fn use_canary_bin_path(local: bool) -> Result<()> {
     if !bin_path.parent().unwrap().exists() {
       fs::create_dir_all(bin_path.parent().unwrap()).unwrap();
     }
-    if bin_path.exists() {
+    // a link to a removed toolchain does not exist, but is still in the way
+    if bin_path.symlink_metadata().is_ok() {
       fs::remove_file(&bin_path)?;
     }
     fs::hard_link(&canary_dir, &bin_path)?;
@@ -140,7 +113,8 @@ pub fn use_this_bin_path(exe_path: &Path, version: &Version, raw_version: String
     if !bin_path.parent().unwrap().exists() {
       fs::create_dir_all(bin_path.parent().unwrap()).unwrap();
     }
-    if bin_path.exists() {
+    // a link to a removed toolchain does not exist, but is still in the way
+    if bin_path.symlink_metadata().is_ok() {
       fs::remove_file(&bin_path)?;
     }
     fs::hard_link(exe_path, &bin_path)?;
@@ -150,13 +124,70 @@ pub fn use_this_bin_path(exe_path: &Path, version: &Version, raw_version: String
   })
 }
 
-fn check_exe(exe_path: &Path, expected_version: &Version) -> Result<()> {
+/// point `deno` at a linked toolchain,
+/// it is symlinked so a rebuild of the toolchain is picked up without running `dvm use` again.
+pub fn use_toolchain(toolchain: &Toolchain, raw_version: String, local: bool) -> Result<()> {
+  let version = exe_version(&toolchain.path)?;
+  run_with_spinner(
+    "Processing".to_string(),
+    format!("Now using deno {} ({})", toolchain.name, version),
+    |_| {
+      let bin_path = deno_bin_path();
+      fs::create_dir_all(bin_path.parent().unwrap())?;
+      if bin_path.symlink_metadata().is_ok() {
+        fs::remove_file(&bin_path)?;
+      }
+      link_exe(&toolchain.path, &bin_path)?;
+
+      rc_update(local, DVM_CONFIGRC_KEY_DENO_VERSION, raw_version.as_str())?;
+      Ok(())
+    },
+  )
+}
+
+#[cfg(unix)]
+fn link_exe(target: &Path, link: &Path) -> std::io::Result<()> {
+  std::os::unix::fs::symlink(target, link)
+}
+
+/// symlinks need extra privileges on windows, a copy has to do without them
+#[cfg(windows)]
+fn link_exe(target: &Path, link: &Path) -> std::io::Result<()> {
+  std::os::windows::fs::symlink_file(target, link).or_else(|_| fs::copy(target, link).map(|_| ()))
+}
+
+/// run `deno -V` and make sure the executable reports the expected version
+pub fn check_exe(exe_path: &Path, expected_version: &Version) -> Result<()> {
+  let version = exe_version(exe_path)?;
+  if &version != expected_version {
+    anyhow::bail!(
+      "{} reports `deno {}`, expected `deno {}`",
+      exe_path.display(),
+      version,
+      expected_version
+    );
+  }
+  Ok(())
+}
+
+/// run `deno -V` and parse the version the executable reports
+pub fn exe_version(exe_path: &Path) -> Result<Version> {
   let output = Command::new(exe_path)
     .arg("-V")
     .stderr(std::process::Stdio::inherit())
-    .output()?;
+    .output()
+    .map_err(|err| anyhow::anyhow!("Failed to run `{} -V`: {}", exe_path.display(), err))?;
+  if !output.status.success() {
+    anyhow::bail!("`{} -V` exited with {}", exe_path.display(), output.status);
+  }
   let stdout = String::from_utf8(output.stdout)?;
-  assert!(output.status.success());
-  assert_eq!(stdout.trim(), format!("deno {}", expected_version));
-  Ok(())
+  let Some(version) = stdout.trim().strip_prefix("deno ") else {
+    anyhow::bail!(
+      "{} reports `{}`, expected `deno <version>`",
+      exe_path.display(),
+      stdout.trim()
+    );
+  };
+  Version::parse(version.trim())
+    .map_err(|_| anyhow::anyhow!("{} reports an invalid version `{}`", exe_path.display(), version))
 }
@@ -1,10 +1,11 @@
 use crate::consts::{
-  DVM_CONFIGRC_FILENAME, DVM_CONFIGRC_KEY_DENO_VERSION, DVM_CONFIGRC_KEY_REGISTRY_BINARY,
+  DVM_CONFIGRC_FILENAME, DVM_CONFIGRC_KEYS, DVM_CONFIGRC_KEY_DENO_VERSION, DVM_CONFIGRC_KEY_REGISTRY_BINARY,
   DVM_CONFIGRC_KEY_REGISTRY_VERSION,
 };
 use crate::consts::{REGISTRY_LIST_OFFICIAL, REGISTRY_OFFICIAL};
 use std::fs;
 use std::io;
+use std::path::PathBuf;
 
 /// check global rc file exists
 pub fn rc_exists() -> bool {
@@ -73,6 +74,22 @@ pub fn rc_get(key: &str) -> io::Result<String> {
     .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "key not found"))
 }
 
+/// like `rc_get_with_fix`, together with the absolute path of the rc file the value was read from
+pub fn rc_get_located(key: &str) -> io::Result<(PathBuf, String)> {
+  let get = || {
+    let (path, content) = rc_content_cascade_located()?;
+    let value = rc_parse(content.as_str())
+      .iter()
+      .find_map(|(k, v)| if k == &key { Some(v.to_string()) } else { None })
+      .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "key not found"))?;
+    Ok((fs::canonicalize(&path).unwrap_or(path), value))
+  };
+  if !rc_exists() {
+    rc_init()?;
+  }
+  get().or_else(|err| rc_fix().and_then(|_| get()).map_err(|_| err))
+}
+
 /// get value by key from configuration with a possible fix
 /// first try to get from current folder
 /// if not found, try to get from home folder
@@ -113,7 +130,6 @@ pub fn rc_update(is_local: bool, key: &str, value: &str) -> io::Result<()> {
 }
 
 /// remove key value pair from config file
-#[allow(dead_code)]
 pub fn rc_remove(is_local: bool, key: &str) -> io::Result<()> {
   let (config_path, content) = rc_content(is_local);
   let Ok(content) = content else {
@@ -160,7 +176,19 @@ fn rc_content(is_local: bool) -> (std::path::PathBuf, io::Result<String>) {
 }
 
 fn rc_content_cascade() -> io::Result<String> {
-  rc_content(true).1.or_else(|_| rc_content(false).1)
+  rc_content_cascade_located().map(|(_, content)| content)
+}
+
+/// the content of the local rc file, or of the user-wide one if there is no local one, and its path
+fn rc_content_cascade_located() -> io::Result<(PathBuf, String)> {
+  let (local_path, local) = rc_content(true);
+  match local {
+    Ok(content) => Ok((local_path, content)),
+    Err(_) => {
+      let (path, content) = rc_content(false);
+      content.map(|content| (path, content))
+    }
+  }
 }
 
 /// remove all key value pair that ain't supported by dvm from config file
@@ -180,11 +208,7 @@ pub fn rc_clean(is_local: bool) -> io::Result<()> {
   let config = rc_parse(content.as_str());
   let config = config
     .iter()
-    .filter(|(k, _)| {
-      k == &DVM_CONFIGRC_KEY_DENO_VERSION
-        || k == &DVM_CONFIGRC_KEY_REGISTRY_BINARY
-        || k == &DVM_CONFIGRC_KEY_REGISTRY_VERSION
-    })
+    .filter(|(k, _)| DVM_CONFIGRC_KEYS.contains(k))
     .collect::<Vec<_>>();
 
   let config = config
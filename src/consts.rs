@@ -2,21 +2,79 @@ pub const REGISTRY_OFFICIAL: &str = "https://dl.deno.land/";
 pub const REGISTRY_CN: &str = "https://dl.deno.js.cn/";
 pub const REGISTRY_LIST_OFFICIAL: &str = "https://deno.com/versions.json";
 pub const REGISTRY_LIST_CN: &str = "https://dl.deno.js.cn/versions.json";
+pub const REGISTRY_LTS_OFFICIAL: &str = "https://github.com/denoland/deno/releases?q=LTS";
 
 pub const REGISTRY_LATEST_RELEASE_PATH: &str = "release-latest.txt";
 pub const REGISTRY_LATEST_CANARY_PATH: &str = "canary-latest.txt";
+pub const REGISTRY_VERSIONS_PATH: &str = "versions.json";
+pub const REGISTRY_RELEASE_TEMPLATE_DEFAULT: &str = "release/v{version}/{archive}";
+pub const REGISTRY_CANARY_TEMPLATE_DEFAULT: &str = "canary/{version}/{archive}";
 pub const REGISTRY_NAME_CN: &str = "cn";
 pub const REGISTRY_NAME_OFFICIAL: &str = "official";
+pub const REGISTRY_NAME_AUTO: &str = "auto";
+pub const REGISTRY_SERVE_PORT_DEFAULT: u16 = 8080;
+pub const REGISTRY_SERVE_BIND_DEFAULT: &str = "127.0.0.1";
+/// requests handled at once by `dvm registry serve`, the rest wait for a free worker
+pub const REGISTRY_SERVE_WORKERS: usize = 8;
 
 pub const DVM_CACHE_PATH_PREFIX: &str = "versions";
 pub const DVM_CACHE_REMOTE_PATH: &str = "cached-remote-versions.json";
+pub const DVM_CACHE_LTS_PATH: &str = "cached-lts-version.txt";
 pub const DVM_CANARY_PATH_PREFIX: &str = "canary";
+pub const DVM_STAGING_PATH_PREFIX: &str = ".staging";
+pub const DVM_DOWNLOAD_PATH_PREFIX: &str = "downloads";
+pub const DVM_ARCHIVES_PATH_PREFIX: &str = "archives";
+pub const DVM_REGISTRY_PATH_PREFIX: &str = "registry";
 pub const DVM_CACHE_INVALID_TIMEOUT: u128 = 60 * 60 * 24 * 7;
+pub const DVM_LOCK_FILENAME: &str = ".lock";
+pub const DVM_CREDENTIALS_FILENAME: &str = "credentials.json";
+pub const DVM_LOCK_TIMEOUT_DEFAULT: u64 = 120;
 
 pub const DVM_CONFIGRC_FILENAME: &str = ".dvmrc";
 pub const DVM_CONFIGRC_KEY_DENO_VERSION: &str = "deno_version";
 pub const DVM_CONFIGRC_KEY_REGISTRY_VERSION: &str = "registry_version";
 pub const DVM_CONFIGRC_KEY_REGISTRY_BINARY: &str = "registry_binary";
+pub const DVM_CONFIGRC_KEY_REGISTRY_LTS: &str = "registry_lts";
+pub const DVM_CONFIGRC_KEY_REGISTRY_RELEASE_TEMPLATE: &str = "registry_release_template";
+pub const DVM_CONFIGRC_KEY_REGISTRY_CANARY_TEMPLATE: &str = "registry_canary_template";
+pub const DVM_CONFIGRC_KEY_HTTP_CONNECT_TIMEOUT: &str = "http_connect_timeout";
+pub const DVM_CONFIGRC_KEY_HTTP_READ_TIMEOUT: &str = "http_read_timeout";
+pub const DVM_CONFIGRC_KEY_HTTP_RETRIES: &str = "http_retries";
+pub const DVM_CONFIGRC_KEY_HTTP_RETRY_BACKOFF: &str = "http_retry_backoff";
+pub const DVM_CONFIGRC_KEY_PROXY: &str = "proxy";
+pub const DVM_CONFIGRC_KEY_CA_FILE: &str = "ca_file";
+pub const DVM_CONFIGRC_KEY_ARCHIVE_CACHE_SIZE: &str = "archive_cache_size";
+pub const DVM_CONFIGRC_KEY_REQUIRE_CHECKSUM: &str = "require_checksum";
+
+/// every key dvm understands, anything else is removed by `dvm clean`
+pub const DVM_CONFIGRC_KEYS: &[&str] = &[
+  DVM_CONFIGRC_KEY_DENO_VERSION,
+  DVM_CONFIGRC_KEY_REGISTRY_VERSION,
+  DVM_CONFIGRC_KEY_REGISTRY_BINARY,
+  DVM_CONFIGRC_KEY_REGISTRY_LTS,
+  DVM_CONFIGRC_KEY_REGISTRY_RELEASE_TEMPLATE,
+  DVM_CONFIGRC_KEY_REGISTRY_CANARY_TEMPLATE,
+  DVM_CONFIGRC_KEY_HTTP_CONNECT_TIMEOUT,
+  DVM_CONFIGRC_KEY_HTTP_READ_TIMEOUT,
+  DVM_CONFIGRC_KEY_HTTP_RETRIES,
+  DVM_CONFIGRC_KEY_HTTP_RETRY_BACKOFF,
+  DVM_CONFIGRC_KEY_PROXY,
+  DVM_CONFIGRC_KEY_CA_FILE,
+  DVM_CONFIGRC_KEY_ARCHIVE_CACHE_SIZE,
+  DVM_CONFIGRC_KEY_REQUIRE_CHECKSUM,
+];
+
+/// seconds
+pub const HTTP_CONNECT_TIMEOUT_DEFAULT: u64 = 30;
+/// seconds
+pub const HTTP_READ_TIMEOUT_DEFAULT: u64 = 60;
+pub const HTTP_RETRIES_DEFAULT: u32 = 3;
+/// milliseconds, doubled after every failed attempt
+pub const HTTP_RETRY_BACKOFF_DEFAULT: u64 = 1000;
+/// MiB, 0 disables the archive cache
+pub const ARCHIVE_CACHE_SIZE_DEFAULT: u64 = 1024;
+/// versions downloaded at once by `dvm install` with several versions
+pub const INSTALL_JOBS_DEFAULT: u32 = 4;
 
 pub const DVM_VERSION_SELF: &str = "self";
 pub const DVM_VERSION_CANARY: &str = "canary";
@@ -33,6 +91,29 @@ cfg_if::cfg_if! {
   }
 }
 
+cfg_if::cfg_if! {
+  if #[cfg(windows)] {
+    pub const ARCHIVE_NAME: &str = "deno-x86_64-pc-windows-msvc.zip";
+  } else if #[cfg(all(target_os = "macos", target_arch = "aarch64"))] {
+    pub const ARCHIVE_NAME: &str = "deno-aarch64-apple-darwin.zip";
+  } else if #[cfg(all(target_os = "macos", target_arch = "x86_64"))] {
+    pub const ARCHIVE_NAME: &str = "deno-x86_64-apple-darwin.zip";
+  } else if #[cfg(all(target_os = "linux", target_arch = "x86_64"))] {
+    pub const ARCHIVE_NAME: &str = "deno-x86_64-unknown-linux-gnu.zip";
+  } else if #[cfg(all(target_os = "linux", target_arch = "aarch64"))] {
+    pub const ARCHIVE_NAME: &str = "deno-aarch64-unknown-linux-gnu.zip";
+  }
+}
+
+/// the targets `dvm mirror sync` knows, with the archive each one is released as
+pub const DENO_TARGETS: &[(&str, &str)] = &[
+  ("linux-x64", "deno-x86_64-unknown-linux-gnu.zip"),
+  ("linux-arm64", "deno-aarch64-unknown-linux-gnu.zip"),
+  ("darwin-x64", "deno-x86_64-apple-darwin.zip"),
+  ("darwin-arm64", "deno-aarch64-apple-darwin.zip"),
+  ("windows-x64", "deno-x86_64-pc-windows-msvc.zip"),
+];
+
 pub const AFTER_HELP: &str = "\x1b[33mEXAMPLE:\x1b[39m
   dvm install 1.3.2     Install v1.3.2 release
   dvm install           Install the latest available version
@@ -0,0 +1,57 @@
+use crate::consts::{DVM_LOCK_FILENAME, DVM_LOCK_TIMEOUT_DEFAULT};
+use crate::utils::dvm_root;
+use anyhow::Result;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::time::{Duration, Instant};
+
+/// An advisory lock on `DVM_DIR`, held by every command that changes its state.
+/// The lock is released when this value is dropped.
+pub struct DvmLock {
+  _file: File,
+}
+
+/// how long to wait for another dvm process, `DVM_LOCK_TIMEOUT` in seconds
+fn lock_timeout() -> Duration {
+  let seconds = std::env::var("DVM_LOCK_TIMEOUT")
+    .ok()
+    .and_then(|it| it.trim().parse::<u64>().ok())
+    .unwrap_or(DVM_LOCK_TIMEOUT_DEFAULT);
+  Duration::from_secs(seconds)
+}
+
+/// acquire the lock on `DVM_DIR`,
+/// waiting for other dvm processes to finish until the timeout is reached.
+pub fn acquire() -> Result<DvmLock> {
+  let root = dvm_root();
+  fs::create_dir_all(&root)?;
+  let path = root.join(DVM_LOCK_FILENAME);
+  let file = OpenOptions::new()
+    .create(true)
+    .truncate(false)
+    .write(true)
+    .open(&path)?;
+
+  let timeout = lock_timeout();
+  let started = Instant::now();
+  let mut notified = false;
+  loop {
+    match file.try_lock() {
+      Ok(()) => return Ok(DvmLock { _file: file }),
+      Err(TryLockError::WouldBlock) => {
+        if started.elapsed() >= timeout {
+          anyhow::bail!(
+            "Timed out after {}s waiting for another dvm process to release {}",
+            timeout.as_secs(),
+            path.display()
+          );
+        }
+        if !notified {
+          eprintln!("Waiting for another dvm process to finish...");
+          notified = true;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+      }
+      Err(TryLockError::Error(err)) => return Err(err.into()),
+    }
+  }
+}
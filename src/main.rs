@@ -1,10 +1,16 @@
 extern crate core;
 
+mod archives;
 mod cli;
 mod commands;
 mod configrc;
 mod consts;
+mod credentials;
+mod http;
+mod lock;
 mod meta;
+mod oci;
+mod resolve;
 mod utils;
 pub mod version;
 
@@ -35,21 +41,55 @@ pub fn main() {
   let Ok(cli) = cli::cli_parse(&mut meta) else {
     return;
   };
+  if cli.offline {
+    http::set_offline();
+  }
+
+  // hold the lock on DVM_DIR until the command finishes
+  let _lock = if cli.command.is_mutating() {
+    match lock::acquire() {
+      Ok(lock) => {
+        // another process may have changed the metadata while we were waiting
+        meta.reload();
+        Some(lock)
+      }
+      Err(err) => {
+        eprintln!("\x1b[31merror:\x1b[39m: {}", err);
+        std::process::exit(1);
+      }
+    }
+  } else {
+    None
+  };
 
   let result = match cli.command {
     Commands::Completions { shell } => commands::completions::exec(&mut Cli::command(), shell),
     Commands::Info => commands::info::exec(),
-    Commands::Install { no_use, version } => run_with_spinner(
-      format!("Installing {}", version.clone().unwrap_or_else(|| "latest".to_string())),
-      "Installed".to_string(),
-      |stop_with_error| match commands::install::exec(&meta, no_use, version) {
-        Ok(ok) => Ok(ok),
-        Err(err) => stop_with_error(format!("Failed to install: {}", err)),
-      },
-    ),
+    // not wrapped in a spinner, the download shows its own progress bar
+    Commands::Install {
+      no_use,
+      mut versions,
+      jobs,
+      from_file,
+      from_binary,
+    } => {
+      let several = versions.len() > 1 || versions.first().is_some_and(|it| resolve::is_range(&meta, it));
+      let result = match (from_file, from_binary) {
+        (Some(_), _) | (_, Some(_)) if versions.len() > 1 => Err(anyhow::anyhow!(
+          "Only one version can be installed from an archive or a binary"
+        )),
+        (Some(archive), _) => commands::install::exec_from_file(no_use, &archive, versions.pop()),
+        (_, Some(binary)) => commands::install::exec_from_binary(no_use, &binary, versions.pop()),
+        // several versions are installed at once and none of them is used
+        _ if several => commands::install::exec_many(&mut meta, &versions, jobs as usize),
+        _ => commands::install::exec(&mut meta, no_use, versions.pop()),
+      };
+      result.map_err(|err| anyhow::anyhow!("Failed to install: {}", err))
+    }
     Commands::List => commands::list::exec(),
     Commands::ListRemote => commands::list::exec_remote(),
-    Commands::Uninstall { version } => commands::uninstall::exec(version),
+    Commands::Uninstall { version } => commands::uninstall::exec(&mut meta, version),
+    Commands::Link { name, path } => commands::link::exec(&mut meta, name, path),
     Commands::Use { version, write_local } => commands::use_version::exec(&mut meta, version, write_local),
     Commands::Alias { command } => commands::alias::exec(&mut meta, command),
     Commands::Activate => commands::activate::exec(&mut meta),
@@ -75,6 +115,7 @@ pub fn main() {
       /* unused */
       Ok(())
     }
+    Commands::Resolve { version, explain, json } => commands::resolve::exec(&meta, version, explain, json),
     Commands::Clean => {
       run_with_spinner(
         "Cleaning...".to_string(),
@@ -87,6 +128,8 @@ pub fn main() {
     }
 
     Commands::Registry { command } => commands::registry::exec(&mut meta, command),
+    Commands::Cache { command } => commands::cache::exec(command),
+    Commands::Mirror { command } => commands::mirror::exec(command),
     Commands::Update => run_with_spinner("Updating cache...".to_string(), "Update success".to_string(), |fail| {
       match commands::update::exec(&mut meta) {
         Ok(ok) => Ok(ok),
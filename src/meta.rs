@@ -1,10 +1,12 @@
-use crate::consts::DVM_CACHE_INVALID_TIMEOUT;
-use crate::utils::{deno_version_path, dvm_root, dvm_versions, now};
-use crate::version::VersionArg;
+use crate::consts::{
+  DVM_CACHE_INVALID_TIMEOUT, REGISTRY_CN, REGISTRY_LIST_CN, REGISTRY_LIST_OFFICIAL, REGISTRY_NAME_CN,
+  REGISTRY_NAME_OFFICIAL, REGISTRY_OFFICIAL,
+};
+use crate::utils::{deno_version_path, dvm_root, dvm_versions, now, write_atomic};
 use colored::Colorize;
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
-use std::fs::{create_dir_all, read_to_string, write};
+use std::fs::{create_dir_all, read_to_string};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
@@ -68,10 +70,70 @@ impl ToVersionReq for Alias {
   }
 }
 
+/// sha256 digest of the archive a version was installed from,
+/// recorded after the download has been verified against the registry.
+#[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug)]
+pub struct Checksum {
+  pub version: String,
+  pub sha256: String,
+}
+
+/// A deno executable outside of `DVM_DIR`, e.g. a local build,
+/// registered with `dvm link` and used by its name like a version.
+#[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug)]
+pub struct Toolchain {
+  pub name: String,
+  pub path: PathBuf,
+}
+
+/// A registry saved under a name with `dvm registry add`, its credentials are kept in the credentials file.
+#[derive(Clone, Eq, PartialEq, Deserialize, Serialize, Debug)]
+pub struct Registry {
+  pub name: String,
+  pub binary: String,
+  pub version: String,
+  /// where the latest LTS version is looked up, the Deno releases if not present
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub lts: Option<String>,
+  /// where a release archive is in the binary registry, `release/v{version}/{archive}` if not present
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub release_template: Option<String>,
+  /// where a canary archive is in the binary registry, `canary/{version}/{archive}` if not present
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub canary_template: Option<String>,
+}
+
+impl Registry {
+  fn predefined(name: &str, binary: &str, version: &str) -> Self {
+    Registry {
+      name: name.to_string(),
+      binary: binary.to_string(),
+      version: version.to_string(),
+      lts: None,
+      release_template: None,
+      canary_template: None,
+    }
+  }
+
+  pub fn official() -> Self {
+    Registry::predefined(REGISTRY_NAME_OFFICIAL, REGISTRY_OFFICIAL, REGISTRY_LIST_OFFICIAL)
+  }
+
+  pub fn cn() -> Self {
+    Registry::predefined(REGISTRY_NAME_CN, REGISTRY_CN, REGISTRY_LIST_CN)
+  }
+}
+
 #[derive(Clone, Default, Eq, PartialEq, Deserialize, Serialize)]
 pub struct DvmMeta {
   pub versions: Vec<VersionMapping>,
   pub alias: Vec<Alias>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub checksums: Vec<Checksum>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub toolchains: Vec<Toolchain>,
+  #[serde(default, skip_serializing_if = "Vec::is_empty")]
+  pub registries: Vec<Registry>,
 }
 
 impl DvmMeta {
@@ -90,7 +152,12 @@ impl DvmMeta {
         if let Ok(mut config) = config {
           let mut i = 0;
           while i < config.versions.len() {
-            if !deno_version_path(&Version::parse(&config.versions[i].current).unwrap()).exists() {
+            let current = &config.versions[i].current;
+            let exists = match Version::parse(current) {
+              Ok(version) => deno_version_path(&version).exists(),
+              Err(_) => config.get_toolchain(current).is_some(),
+            };
+            if !exists {
               config.versions.remove(i);
             } else {
               i += 1;
@@ -213,17 +280,68 @@ impl DvmMeta {
     self.get_alias(name).is_some()
   }
 
-  /// get the semver range of alias
-  pub fn get_alias(&self, name: &str) -> Option<VersionArg> {
+  /// get what an alias points to, a version, a range, `lts` or the name of a toolchain
+  pub fn get_alias(&self, name: &str) -> Option<String> {
     if DEFAULT_ALIAS.contains_key(name) {
-      VersionArg::from_str(DEFAULT_ALIAS[name]).ok()
+      Some(DEFAULT_ALIAS[name].to_string())
     } else {
       self
         .alias
         .iter()
-        .position(|it| it.name == name)
-        .map(|index| VersionArg::from_str(&self.alias[index].required).unwrap())
+        .find(|it| it.name == name)
+        .map(|it| it.required.clone())
+    }
+  }
+
+  /// register a toolchain, replacing the one with the same name
+  pub fn set_toolchain(&mut self, name: String, path: PathBuf) {
+    let result = self.toolchains.iter().position(|it| it.name == name);
+    if let Some(index) = result {
+      self.toolchains[index] = Toolchain { name, path };
+    } else {
+      self.toolchains.push(Toolchain { name, path });
+    }
+
+    self.save_and_reload();
+  }
+
+  pub fn get_toolchain(&self, name: &str) -> Option<&Toolchain> {
+    self.toolchains.iter().find(|it| it.name == name)
+  }
+
+  /// unregister a toolchain, the executable itself is left alone
+  pub fn delete_toolchain(&mut self, name: &str) -> bool {
+    let len = self.toolchains.len();
+    self.toolchains.retain(|it| it.name != name);
+    let deleted = len != self.toolchains.len();
+
+    self.save_and_reload();
+    deleted
+  }
+
+  /// save a named registry, replacing the one with the same name
+  pub fn set_registry(&mut self, registry: Registry) {
+    let result = self.registries.iter().position(|it| it.name == registry.name);
+    if let Some(index) = result {
+      self.registries[index] = registry;
+    } else {
+      self.registries.push(registry);
     }
+
+    self.save_and_reload();
+  }
+
+  pub fn get_registry(&self, name: &str) -> Option<&Registry> {
+    self.registries.iter().find(|it| it.name == name)
+  }
+
+  /// forget a named registry, returns it if there was one
+  pub fn delete_registry(&mut self, name: &str) -> Option<Registry> {
+    let index = self.registries.iter().position(|it| it.name == name)?;
+    let registry = self.registries.remove(index);
+
+    self.save_and_reload();
+    Some(registry)
   }
 
   /// delete a alias
@@ -236,12 +354,16 @@ impl DvmMeta {
     self.save_and_reload();
   }
 
-  pub fn resolve_version_req(&self, required: &str) -> VersionArg {
-    if self.has_alias(required) {
-      self.get_alias(required).unwrap()
+  /// record the verified sha256 digest of a version's archive
+  pub fn set_checksum(&mut self, version: String, sha256: String) {
+    let result = self.checksums.iter().position(|it| it.version == version);
+    if let Some(index) = result {
+      self.checksums[index] = Checksum { version, sha256 };
     } else {
-      VersionArg::from_str(required).unwrap()
+      self.checksums.push(Checksum { version, sha256 });
     }
+
+    self.save_and_reload();
   }
 
   /// reload from disk
@@ -249,6 +371,9 @@ impl DvmMeta {
     let new = DvmMeta::new();
     self.versions = new.versions;
     self.alias = new.alias;
+    self.checksums = new.checksums;
+    self.toolchains = new.toolchains;
+    self.registries = new.registries;
   }
 
   /// write to disk
@@ -258,7 +383,7 @@ impl DvmMeta {
     if !dir_path.exists() {
       create_dir_all(dir_path).unwrap();
     }
-    write(file_path, serde_json::to_string_pretty(self).unwrap()).unwrap();
+    write_atomic(&file_path, serde_json::to_string_pretty(self).unwrap()).unwrap();
   }
 
   pub fn save_and_reload(&mut self) {
@@ -348,5 +473,23 @@ mod tests {
     assert_eq!(parsed.versions[1].current, "1.2.0");
     assert!(parsed.versions[1].try_to_version_req().is_ok());
     assert!(parsed.versions[1].is_valid_mapping());
+    assert!(parsed.checksums.is_empty());
+  }
+
+  #[test]
+  fn test_checksums_config() {
+    let mut conf = DvmMeta::default();
+    conf.checksums.push(Checksum {
+      version: "1.0.1".to_string(),
+      sha256: "ab".repeat(32),
+    });
+    let result = serde_json::to_string(&conf).unwrap();
+    assert_eq!(
+      result,
+      format!(
+        "{{\"versions\":[],\"alias\":[],\"checksums\":[{{\"version\":\"1.0.1\",\"sha256\":\"{}\"}}]}}",
+        "ab".repeat(32)
+      )
+    );
   }
 }
@@ -0,0 +1,430 @@
+use crate::credentials::{self, redact_url};
+use crate::http::{self, Response};
+use crate::utils::{sha256_hex, Reporter};
+use anyhow::Result;
+use semver::Version;
+use serde::Deserialize;
+use std::collections::HashMap;
+use url::Url;
+
+/// the manifests a tag can point to, an index lists one manifest per platform
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.manifest.v1+json, \
+  application/vnd.oci.image.index.v1+json, \
+  application/vnd.docker.distribution.manifest.v2+json, \
+  application/vnd.docker.distribution.manifest.list.v2+json";
+
+/// the annotation naming the file a layer holds, set by `oras push`
+const TITLE_ANNOTATION: &str = "org.opencontainers.image.title";
+
+/// whether a registry is an OCI repository rather than an http directory layout
+pub fn is_oci(registry: &str) -> bool {
+  let primary = http::primary(registry);
+  primary.starts_with("oci://") || primary.starts_with("oci+http://")
+}
+
+/// A repository of an OCI registry, `oci://harbor.example.com/toolchains/deno`.
+/// `oci+http://` talks plain http to registries without tls, like a local `registry:2`.
+#[derive(Debug, Eq, PartialEq)]
+struct Repository {
+  /// the url it was given as, credentials are stored for it
+  url: String,
+  /// `https://host[:port]` of the registry api
+  base: String,
+  name: String,
+}
+
+impl Repository {
+  fn parse(registry: &str) -> Result<Self> {
+    let url = http::primary(registry).trim_end_matches('/');
+    let (scheme, rest) = if let Some(rest) = url.strip_prefix("oci+http://") {
+      ("http", rest)
+    } else if let Some(rest) = url.strip_prefix("oci://") {
+      ("https", rest)
+    } else {
+      anyhow::bail!("{} is not an oci:// registry", redact_url(url));
+    };
+    match rest.split_once('/') {
+      Some((host, name)) if !host.is_empty() && !name.is_empty() => Ok(Repository {
+        url: url.to_string(),
+        base: format!("{}://{}", scheme, host),
+        name: name.to_string(),
+      }),
+      _ => anyhow::bail!(
+        "{} names no repository, like oci://harbor.example.com/toolchains/deno",
+        redact_url(url)
+      ),
+    }
+  }
+
+  fn api(&self, path: &str) -> String {
+    format!("{}/v2/{}/{}", self.base, self.name, path)
+  }
+}
+
+#[derive(Deserialize)]
+struct Tags {
+  #[serde(default)]
+  tags: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+struct Token {
+  token: Option<String>,
+  access_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Manifest {
+  /// the manifests of an index
+  #[serde(default)]
+  manifests: Vec<Descriptor>,
+  #[serde(default)]
+  layers: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+struct Descriptor {
+  digest: String,
+  #[serde(default)]
+  platform: Option<Platform>,
+  #[serde(default)]
+  annotations: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct Platform {
+  os: String,
+  architecture: String,
+}
+
+/// Sends the requests of one repository with the token the registry hands out on the first `401`.
+struct Client {
+  repository: Repository,
+  authorization: Option<String>,
+}
+
+impl Client {
+  fn new(registry: &str) -> Result<Self> {
+    Ok(Client {
+      repository: Repository::parse(registry)?,
+      authorization: None,
+    })
+  }
+
+  fn get(&mut self, url: &str, accept: Option<&str>) -> Result<Response> {
+    let response = self.send(url, accept)?;
+    if response.status() != 401 {
+      return Ok(response);
+    }
+
+    let challenge = response.header("WWW-Authenticate").unwrap_or_default().to_string();
+    self.authorization = Some(self.authorize(&challenge)?);
+    let response = self.send(url, accept)?;
+    if response.status() == 401 {
+      anyhow::bail!(
+        "{} refused the credentials, see `dvm registry login`",
+        redact_url(&self.repository.url)
+      );
+    }
+    Ok(response)
+  }
+
+  fn send(&self, url: &str, accept: Option<&str>) -> Result<Response> {
+    let mut headers = Vec::new();
+    if let Some(authorization) = &self.authorization {
+      headers.push(("Authorization", authorization.as_str()));
+    }
+    if let Some(accept) = accept {
+      headers.push(("Accept", accept));
+    }
+    http::get_with_headers(url, &headers)
+  }
+
+  /// the authorization for a `WWW-Authenticate` challenge, a bearer token is requested with the stored credentials
+  fn authorize(&self, challenge: &str) -> Result<String> {
+    let credentials = credentials::authorization_for(&self.repository.url)?;
+    let Some(params) = challenge.strip_prefix("Bearer ") else {
+      return credentials.ok_or_else(|| {
+        anyhow::anyhow!(
+          "{} requires credentials, see `dvm registry login`",
+          redact_url(&self.repository.url)
+        )
+      });
+    };
+
+    let params = challenge_params(params);
+    let realm = params
+      .get("realm")
+      .ok_or_else(|| anyhow::anyhow!("The registry sent no token realm: {}", challenge))?;
+    let scope = params
+      .get("scope")
+      .cloned()
+      .unwrap_or_else(|| format!("repository:{}:pull", self.repository.name));
+    let mut token_url = Url::parse(realm)?;
+    if let Some(service) = params.get("service") {
+      token_url.query_pairs_mut().append_pair("service", service);
+    }
+    token_url.query_pairs_mut().append_pair("scope", &scope);
+
+    let headers: Vec<(&str, &str)> = credentials.iter().map(|it| ("Authorization", it.as_str())).collect();
+    let response = http::get_with_headers(token_url.as_str(), &headers)?;
+    if response.status() >= 400 {
+      anyhow::bail!(
+        "Failed to get a token for {}: {}, see `dvm registry login`",
+        redact_url(&self.repository.url),
+        response.status()
+      );
+    }
+    let token: Token = serde_json::from_str(&response.into_string()?)?;
+    token
+      .token
+      .or(token.access_token)
+      .map(|it| format!("Bearer {}", it))
+      .ok_or_else(|| {
+        anyhow::anyhow!(
+          "The token service of {} sent no token",
+          redact_url(&self.repository.url)
+        )
+      })
+  }
+
+  fn manifest(&mut self, reference: &str) -> Result<Option<Manifest>> {
+    let url = self.repository.api(&format!("manifests/{}", reference));
+    let response = self.get(&url, Some(MANIFEST_TYPES))?;
+    match response.status() {
+      404 => Ok(None),
+      status if status >= 400 => anyhow::bail!("Request to '{}' failed: {}", url, status),
+      _ => Ok(Some(serde_json::from_str(&response.into_string()?)?)),
+    }
+  }
+}
+
+/// the `key="value"` parameters of a `WWW-Authenticate` challenge
+fn challenge_params(params: &str) -> HashMap<String, String> {
+  let mut result = HashMap::new();
+  let mut rest = params.trim();
+  while let Some((key, value)) = rest.split_once('=') {
+    let key = key.trim().trim_start_matches(',').trim().to_string();
+    let value = value.trim_start();
+    let (value, next) = match value.strip_prefix('"') {
+      Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+      None => value.split_once(',').unwrap_or((value, "")),
+    };
+    result.insert(key, value.to_string());
+    rest = next;
+  }
+  result
+}
+
+/// the next page of a paginated list, from a `Link: </v2/...?last=x>; rel="next"` header
+fn next_link(repository: &Repository, link: Option<&str>) -> Option<String> {
+  let link = link?;
+  if !link.contains("rel=\"next\"") {
+    return None;
+  }
+  let target = link.split_once('<')?.1.split_once('>')?.0;
+  if target.starts_with('/') {
+    Some(format!("{}{}", repository.base, target))
+  } else {
+    Some(target.to_string())
+  }
+}
+
+/// every version tagged in the repository, tags that are no version are skipped
+pub fn versions(registry: &str) -> Result<Vec<Version>> {
+  let mut client = Client::new(registry)?;
+  let mut url = Some(client.repository.api("tags/list"));
+  let mut versions = Vec::new();
+  while let Some(page) = url {
+    let response = client.get(&page, None)?;
+    if response.status() >= 400 {
+      anyhow::bail!("Request to '{}' failed: {}", page, response.status());
+    }
+    url = next_link(&client.repository, response.header("Link"));
+    let tags: Tags = serde_json::from_str(&response.into_string()?)?;
+    versions.extend(
+      tags
+        .tags
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|it| Version::parse(it.trim_start_matches('v')).ok()),
+    );
+  }
+  versions.sort_unstable_by(|a, b| b.cmp(a));
+  versions.dedup();
+  Ok(versions)
+}
+
+/// the tags of the repository as a `versions.json`, the version list of the http layout
+pub fn versions_json(registry: &str) -> Result<String> {
+  let cli: Vec<String> = versions(registry)?.iter().map(|it| format!("v{}", it)).collect();
+  Ok(serde_json::json!({ "cli": cli }).to_string())
+}
+
+/// the os and architecture of an archive name, the way OCI platforms name them
+fn platform(archive: &str) -> (&'static str, &'static str) {
+  let os = if archive.contains("apple-darwin") {
+    "darwin"
+  } else if archive.contains("windows") {
+    "windows"
+  } else {
+    "linux"
+  };
+  let architecture = if archive.contains("aarch64") { "arm64" } else { "amd64" };
+  (os, architecture)
+}
+
+/// the layer holding the archive, named by its title or the only, untitled, layer of the manifest
+fn select_layer<'a>(layers: &'a [Descriptor], archive: &str) -> Option<&'a Descriptor> {
+  let title = |layer: &Descriptor| layer.annotations.get(TITLE_ANNOTATION).cloned();
+  match layers {
+    [layer] if title(layer).is_none() => Some(layer),
+    _ => layers.iter().find(|it| title(it).as_deref() == Some(archive)),
+  }
+}
+
+/// pull the archive of a version, tagged `{version}` or `v{version}`,
+/// and verify it against the digest of its blob. returns the archive and its sha256 digest.
+pub fn pull(registry: &str, version: &Version, archive: &str, reporter: &Reporter) -> Result<(Vec<u8>, String)> {
+  let mut client = Client::new(registry)?;
+  let mut found = None;
+  for tag in [version.to_string(), format!("v{}", version)] {
+    if let Some(manifest) = client.manifest(&tag)? {
+      found = Some((tag, manifest));
+      break;
+    }
+  }
+  let Some((tag, mut manifest)) = found else {
+    anyhow::bail!(
+      "{} has no tag {} or v{}",
+      redact_url(&client.repository.url),
+      version,
+      version
+    );
+  };
+
+  if !manifest.manifests.is_empty() {
+    let (os, architecture) = platform(archive);
+    let digest = manifest
+      .manifests
+      .iter()
+      .find(|it| {
+        it.platform
+          .as_ref()
+          .is_some_and(|platform| platform.os == os && platform.architecture == architecture)
+      })
+      .map(|it| it.digest.clone())
+      .ok_or_else(|| {
+        anyhow::anyhow!(
+          "{}:{} has no manifest for {}/{}",
+          client.repository.url,
+          tag,
+          os,
+          architecture
+        )
+      })?;
+    manifest = client.manifest(&digest)?.ok_or_else(|| {
+      anyhow::anyhow!(
+        "{}:{} lists the missing manifest {}",
+        client.repository.url,
+        tag,
+        digest
+      )
+    })?;
+  }
+
+  let layer = select_layer(&manifest.layers, archive)
+    .ok_or_else(|| anyhow::anyhow!("{}:{} has no layer titled {}", client.repository.url, tag, archive))?;
+  let Some(("sha256", expected)) = layer.digest.split_once(':') else {
+    anyhow::bail!("Unsupported digest {}, only sha256 is verified", layer.digest);
+  };
+  let expected = expected.to_ascii_lowercase();
+
+  reporter.println(format!(
+    "downloading {}:{} {}",
+    redact_url(&client.repository.url),
+    tag,
+    layer.digest
+  ));
+  let url = client.repository.api(&format!("blobs/{}", layer.digest));
+  let response = client.get(&url, None)?;
+  if response.status() >= 400 {
+    anyhow::bail!("Download '{}' failed: {}", url, response.status());
+  }
+  let data = response.into_bytes()?;
+  let actual = sha256_hex(&data);
+  if actual != expected {
+    anyhow::bail!("Digest mismatch for {}: expected {}, got {}", url, expected, actual);
+  }
+  reporter.println(format!("Digest verified: {}", layer.digest));
+  Ok((data, actual))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn repositories_are_parsed_from_oci_urls() {
+    assert_eq!(
+      Repository::parse("oci://harbor.corp/toolchains/deno/").unwrap(),
+      Repository {
+        url: "oci://harbor.corp/toolchains/deno".to_string(),
+        base: "https://harbor.corp".to_string(),
+        name: "toolchains/deno".to_string(),
+      }
+    );
+    assert_eq!(
+      Repository::parse("oci+http://localhost:5000/deno")
+        .unwrap()
+        .api("tags/list"),
+      "http://localhost:5000/v2/deno/tags/list"
+    );
+    assert!(Repository::parse("oci://harbor.corp").is_err());
+    assert!(Repository::parse("https://harbor.corp/deno").is_err());
+  }
+
+  #[test]
+  fn challenges_and_links_are_parsed() {
+    let params = challenge_params(
+      r#"realm="https://harbor.corp/service/token",service="harbor-registry",scope="repository:toolchains/deno:pull""#,
+    );
+    assert_eq!(params["realm"], "https://harbor.corp/service/token");
+    assert_eq!(params["service"], "harbor-registry");
+    assert_eq!(params["scope"], "repository:toolchains/deno:pull");
+
+    let repository = Repository::parse("oci://harbor.corp/deno").unwrap();
+    assert_eq!(
+      next_link(
+        &repository,
+        Some(r#"</v2/deno/tags/list?last=v2.1.4&n=100>; rel="next""#)
+      ),
+      Some("https://harbor.corp/v2/deno/tags/list?last=v2.1.4&n=100".to_string())
+    );
+    assert_eq!(next_link(&repository, None), None);
+  }
+
+  #[test]
+  fn layers_are_selected_by_title() {
+    let layer = |digest: &str, title: Option<&str>| Descriptor {
+      digest: digest.to_string(),
+      platform: None,
+      annotations: title
+        .map(|it| HashMap::from([(TITLE_ANNOTATION.to_string(), it.to_string())]))
+        .unwrap_or_default(),
+    };
+    let archive = "deno-x86_64-unknown-linux-gnu.zip";
+
+    let layers = [
+      layer("sha256:a", Some("deno-aarch64-apple-darwin.zip")),
+      layer("sha256:b", Some(archive)),
+    ];
+    assert_eq!(select_layer(&layers, archive).unwrap().digest, "sha256:b");
+    assert_eq!(
+      select_layer(&[layer("sha256:c", None)], archive).unwrap().digest,
+      "sha256:c"
+    );
+    assert!(select_layer(&layers[..1], "deno-x86_64-pc-windows-msvc.zip").is_none());
+    assert_eq!(platform(archive), ("linux", "amd64"));
+  }
+}
@@ -0,0 +1,602 @@
+//! turning a version argument into the deno it stands for,
+//! the same way for `install`, `use`, `exec` and `upgrade`.
+
+use crate::configrc::{rc_get_located, rc_get_with_fix};
+use crate::consts::{
+  DVM_CACHE_LTS_PATH, DVM_CONFIGRC_KEY_DENO_VERSION, DVM_CONFIGRC_KEY_REGISTRY_VERSION, DVM_VERSION_CANARY,
+  DVM_VERSION_LATEST, DVM_VERSION_LTS, DVM_VERSION_SYSTEM, REGISTRY_LIST_OFFICIAL,
+};
+use crate::credentials::redact_url;
+use crate::http;
+use crate::meta::DvmMeta;
+use crate::utils::{best_version, deno_bin_path, deno_canary_path, deno_version_path, dvm_root, is_semver};
+use crate::version::{
+  cached_remote_versions_location, candidate_versions, get_latest_lts_version, get_latest_remote_version,
+  local_versions, lts_source,
+};
+use anyhow::Result;
+use semver::{Version, VersionReq};
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+/// What a version argument asks for, before anything is looked up.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Spec {
+  Exact(Version),
+  /// a semver range, partial versions like `1.40` are ranges too
+  Range(VersionReq),
+  Latest,
+  Lts,
+  Canary,
+  System,
+  /// the name of a toolchain registered with `dvm link`
+  Toolchain(String),
+  /// the name of an alias and what it points to
+  Alias(String, Box<Spec>),
+}
+
+impl Display for Spec {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Spec::Exact(version) => write!(f, "{}", version),
+      Spec::Range(range) => write!(f, "{}", range),
+      Spec::Latest => f.write_str(DVM_VERSION_LATEST),
+      Spec::Lts => f.write_str(DVM_VERSION_LTS),
+      Spec::Canary => f.write_str(DVM_VERSION_CANARY),
+      Spec::System => f.write_str(DVM_VERSION_SYSTEM),
+      Spec::Toolchain(name) => f.write_str(name),
+      Spec::Alias(name, _) => f.write_str(name),
+    }
+  }
+}
+
+/// The deno a version argument stands for.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Resolved {
+  Version(Version),
+  /// a toolchain registered with `dvm link`, used as it is
+  Toolchain {
+    name: String,
+    path: PathBuf,
+  },
+  Canary,
+  /// the deno installed without dvm
+  System,
+}
+
+/// Where the answer came from.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Source {
+  /// the argument was the version itself
+  Exact,
+  /// the highest version matching a range
+  Range {
+    range: VersionReq,
+    candidates: Candidates,
+  },
+  /// the latest stable version listed by the version registry, or installed when offline
+  Latest {
+    registry: String,
+    offline: bool,
+  },
+  /// the latest LTS release, remembered in a cache file for offline mode
+  Lts {
+    source: String,
+    offline: bool,
+  },
+  Canary,
+  System,
+  Toolchain,
+  /// an alias, resolved through what it points to
+  Alias {
+    name: String,
+    target: String,
+    source: Box<Source>,
+  },
+  /// no argument was given, the `deno_version` of a `.dvmrc` file was used
+  Dvmrc {
+    path: PathBuf,
+    key: String,
+    value: String,
+    source: Box<Source>,
+  },
+  /// no argument was given and no `.dvmrc` file has a `deno_version`
+  Default {
+    source: Box<Source>,
+  },
+}
+
+/// The versions a range was matched against.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Candidates {
+  /// the cached version list of the version registry, see `dvm update`
+  Remote { path: PathBuf, count: usize },
+  /// the installed versions and cached archives, in offline mode
+  Local { count: usize },
+  /// the installed versions only, e.g. to uninstall one
+  Installed { count: usize },
+}
+
+/// A version argument, what it resolved to and why.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Resolution {
+  pub input: String,
+  pub resolved: Resolved,
+  pub source: Source,
+}
+
+impl Resolution {
+  /// how the answer was found, one line per step, nothing for an exact version
+  pub fn explain(&self) -> Vec<String> {
+    let mut lines = Vec::new();
+    explain_source(&self.source, &self.resolved, &mut lines);
+    lines
+  }
+
+  /// the executable it resolved to, the one of a version may not be installed yet
+  pub fn binary(&self) -> Option<PathBuf> {
+    match &self.resolved {
+      Resolved::Version(version) => Some(deno_version_path(version)),
+      Resolved::Toolchain { path, .. } => Some(path.clone()),
+      Resolved::Canary => Some(deno_canary_path()),
+      Resolved::System => system_deno().ok(),
+    }
+  }
+}
+
+/// the first deno on `PATH` that is not the one managed by dvm
+pub fn system_deno() -> Result<PathBuf> {
+  let managed = deno_bin_path();
+  which::which_all("deno")?
+    .find(|it| it.parent() != managed.parent())
+    .ok_or_else(|| anyhow::anyhow!("No deno is installed on your system outside of dvm"))
+}
+
+fn explain_source(source: &Source, resolved: &Resolved, lines: &mut Vec<String>) {
+  let answer = match resolved {
+    Resolved::Version(version) => format!("v{}", version),
+    Resolved::Toolchain { name, path } => format!("{} ({})", name, path.display()),
+    Resolved::Canary => DVM_VERSION_CANARY.to_string(),
+    Resolved::System => DVM_VERSION_SYSTEM.to_string(),
+  };
+  match source {
+    Source::Exact | Source::Canary | Source::System | Source::Toolchain => {}
+    Source::Range {
+      range,
+      candidates: Candidates::Remote { count, .. },
+    } => lines.push(format!(
+      "The highest version matching {} out of {} versions in the cached remote list is {}",
+      range, count, answer
+    )),
+    Source::Range {
+      range,
+      candidates: Candidates::Local { count },
+    } => lines.push(format!(
+      "The highest version matching {} out of {} installed or cached versions is {}",
+      range, count, answer
+    )),
+    Source::Range {
+      range,
+      candidates: Candidates::Installed { count },
+    } => lines.push(format!(
+      "The highest version matching {} out of {} installed versions is {}",
+      range, count, answer
+    )),
+    Source::Latest {
+      registry,
+      offline: false,
+    } => lines.push(format!("The latest version listed by {} is {}", registry, answer)),
+    Source::Latest { offline: true, .. } => lines.push(format!("The latest installed version is {}", answer)),
+    Source::Lts { source, offline: false } => {
+      lines.push(format!("The latest LTS version according to {} is {}", source, answer))
+    }
+    Source::Lts { source, offline: true } => lines.push(format!(
+      "The last known LTS version, cached in {}, is {}",
+      source, answer
+    )),
+    Source::Alias { name, target, source } => {
+      lines.push(format!("{} is an alias of {}", name, target));
+      explain_source(source, resolved, lines);
+    }
+    Source::Dvmrc {
+      path,
+      key,
+      value,
+      source,
+    } => {
+      lines.push(format!("Using {}={} from {}", key, value, path.display()));
+      explain_source(source, resolved, lines);
+    }
+    Source::Default { source } => {
+      lines.push(format!(
+        "No .dvmrc file sets {}, using latest",
+        DVM_CONFIGRC_KEY_DENO_VERSION
+      ));
+      explain_source(source, resolved, lines);
+    }
+  }
+}
+
+/// parse a version argument, names of aliases and linked toolchains included
+pub fn parse(meta: &DvmMeta, input: &str) -> Result<Spec> {
+  let input = input.trim();
+  if let Some(spec) = parse_target(meta, input) {
+    return Ok(spec);
+  }
+  if let Some(target) = meta.get_alias(input) {
+    let spec = parse_alias_target(meta, &target)
+      .ok_or_else(|| anyhow::anyhow!("The alias {} points to `{}`, which is not a version", input, target))?;
+    return Ok(Spec::Alias(input.to_string(), Box::new(spec)));
+  }
+  parse_range(input).ok_or_else(|| {
+    anyhow::anyhow!(
+      "`{}` is not a version, a range, an alias or a linked toolchain\ntype `dvm help` for more info",
+      input
+    )
+  })
+}
+
+/// what an alias points to, anything but another alias
+pub fn parse_alias_target(meta: &DvmMeta, target: &str) -> Option<Spec> {
+  parse_target(meta, target).or_else(|| parse_range(target.trim()))
+}
+
+/// everything but aliases, which only ever point to one of these,
+/// the keywords, exact versions and toolchains come before the aliases so they can not be shadowed
+fn parse_target(meta: &DvmMeta, input: &str) -> Option<Spec> {
+  let input = input.trim();
+  match input {
+    DVM_VERSION_LATEST => return Some(Spec::Latest),
+    DVM_VERSION_LTS => return Some(Spec::Lts),
+    DVM_VERSION_CANARY => return Some(Spec::Canary),
+    DVM_VERSION_SYSTEM => return Some(Spec::System),
+    _ => {}
+  }
+  if let Ok(version) = Version::parse(input.strip_prefix('v').unwrap_or(input)) {
+    return Some(Spec::Exact(version));
+  }
+  if let Some(toolchain) = meta.get_toolchain(input) {
+    return Some(Spec::Toolchain(toolchain.name.clone()));
+  }
+  None
+}
+
+/// a partial version or a semver range, `*` is the same as `latest`
+fn parse_range(input: &str) -> Option<Spec> {
+  let range = parse_partial(input).or_else(|| VersionReq::parse(input).ok())?;
+  if range == VersionReq::STAR {
+    Some(Spec::Latest)
+  } else {
+    Some(Spec::Range(range))
+  }
+}
+
+/// `1.40` is `~1.40`, any patch of 1.40, and `2` is `^2`, any 2.x
+fn parse_partial(input: &str) -> Option<VersionReq> {
+  let partial = input.strip_prefix('v').unwrap_or(input);
+  let parts: Vec<&str> = partial.split('.').collect();
+  if parts
+    .iter()
+    .any(|it| it.is_empty() || !it.chars().all(|c| c.is_ascii_digit()))
+  {
+    return None;
+  }
+  match parts.len() {
+    1 => VersionReq::parse(&format!("^{}", partial)).ok(),
+    2 => VersionReq::parse(&format!("~{}", partial)).ok(),
+    _ => None,
+  }
+}
+
+/// resolve a version argument
+pub fn resolve(meta: &DvmMeta, input: &str) -> Result<Resolution> {
+  resolve_parsed(meta, input, parse(meta, input)?)
+}
+
+/// resolve a version argument, with `latest` taken from the cached remote list
+/// instead of the registry, so that running a version does not wait for the network
+pub fn resolve_cached(meta: &DvmMeta, input: &str) -> Result<Resolution> {
+  let spec = match parse(meta, input)? {
+    Spec::Latest => Spec::Range(VersionReq::STAR),
+    spec => spec,
+  };
+  resolve_parsed(meta, input, spec)
+}
+
+fn resolve_parsed(meta: &DvmMeta, input: &str, spec: Spec) -> Result<Resolution> {
+  let (resolved, source) = resolve_spec(meta, &spec)?;
+  Ok(Resolution {
+    input: input.trim().to_string(),
+    resolved,
+    source,
+  })
+}
+
+/// resolve the `deno_version` of the `.dvmrc` file, or `latest` without one
+pub fn resolve_default(meta: &DvmMeta) -> Result<Resolution> {
+  let Ok((path, value)) = rc_get_located(DVM_CONFIGRC_KEY_DENO_VERSION) else {
+    let resolution = resolve(meta, DVM_VERSION_LATEST)?;
+    return Ok(Resolution {
+      source: Source::Default {
+        source: Box::new(resolution.source),
+      },
+      ..resolution
+    });
+  };
+  let resolution = resolve(meta, &value)?;
+  Ok(Resolution {
+    source: Source::Dvmrc {
+      path,
+      key: DVM_CONFIGRC_KEY_DENO_VERSION.to_string(),
+      value: resolution.input.clone(),
+      source: Box::new(resolution.source),
+    },
+    ..resolution
+  })
+}
+
+/// resolve a version argument against the installed versions only, a range picks the highest installed match
+pub fn resolve_installed(meta: &DvmMeta, input: &str) -> Result<Resolution> {
+  let spec = parse(meta, input)?;
+  let (resolved, source) = resolve_installed_spec(meta, &spec)?;
+  Ok(Resolution {
+    input: input.trim().to_string(),
+    resolved,
+    source,
+  })
+}
+
+fn resolve_installed_spec(meta: &DvmMeta, spec: &Spec) -> Result<(Resolved, Source)> {
+  match spec {
+    Spec::Exact(_) | Spec::Toolchain(_) => resolve_spec(meta, spec),
+    Spec::Range(range) => {
+      let versions: Vec<String> = local_versions().into_iter().filter(|it| is_semver(it)).collect();
+      let version = best_version(versions.iter().map(AsRef::as_ref), range.clone())
+        .ok_or_else(|| anyhow::anyhow!("No installed version matches {}", range))?;
+      Ok((
+        Resolved::Version(version),
+        Source::Range {
+          range: range.clone(),
+          candidates: Candidates::Installed { count: versions.len() },
+        },
+      ))
+    }
+    Spec::Alias(name, target) => {
+      let (resolved, source) = resolve_installed_spec(meta, target)?;
+      Ok((
+        resolved,
+        Source::Alias {
+          name: name.clone(),
+          target: target.to_string(),
+          source: Box::new(source),
+        },
+      ))
+    }
+    _ => anyhow::bail!(
+      "`{}` does not name an installed version, give a version or a range",
+      spec
+    ),
+  }
+}
+
+/// whether a version argument is a range that installs every matching release, partial versions are not
+pub fn is_range(meta: &DvmMeta, input: &str) -> bool {
+  matches!(parse(meta, input), Ok(Spec::Range(_))) && parse_partial(input.trim()).is_none()
+}
+
+/// the versions to install for a version argument, every release in a range,
+/// and the one version anything else resolves to
+pub fn expand(meta: &DvmMeta, input: &str) -> Result<Vec<Version>> {
+  match parse(meta, input)? {
+    Spec::Range(range) if parse_partial(input.trim()).is_none() => {
+      let mut versions: Vec<Version> = candidate_versions()?
+        .iter()
+        .filter_map(|it| Version::parse(it).ok())
+        .filter(|it| range.matches(it))
+        .collect();
+      versions.sort();
+      versions.dedup();
+      if versions.is_empty() {
+        anyhow::bail!("No version found for {}", range);
+      }
+      Ok(versions)
+    }
+    spec => match resolve_spec(meta, &spec)?.0 {
+      Resolved::Version(version) => Ok(vec![version]),
+      _ => anyhow::bail!("`{}` can not be installed together with other versions", input.trim()),
+    },
+  }
+}
+
+/// the highest candidate version matching the range, and what the candidates were
+fn resolve_range(range: &VersionReq) -> Result<(Version, Candidates)> {
+  let versions = candidate_versions()?;
+  let candidates = if http::is_offline() {
+    Candidates::Local { count: versions.len() }
+  } else {
+    Candidates::Remote {
+      path: cached_remote_versions_location(),
+      count: versions.len(),
+    }
+  };
+  let version = best_version(versions.iter().map(AsRef::as_ref), range.clone()).ok_or_else(|| {
+    if http::is_offline() {
+      anyhow::anyhow!("No installed or cached version matches {} in offline mode", range)
+    } else {
+      anyhow::anyhow!("No version found for {}", range)
+    }
+  })?;
+  Ok((version, candidates))
+}
+
+fn resolve_spec(meta: &DvmMeta, spec: &Spec) -> Result<(Resolved, Source)> {
+  let offline = http::is_offline();
+  Ok(match spec {
+    Spec::Exact(version) => (Resolved::Version(version.clone()), Source::Exact),
+    Spec::Range(range) => {
+      let (version, candidates) = resolve_range(range)?;
+      (
+        Resolved::Version(version),
+        Source::Range {
+          range: range.clone(),
+          candidates,
+        },
+      )
+    }
+    Spec::Latest => {
+      let registry =
+        rc_get_with_fix(DVM_CONFIGRC_KEY_REGISTRY_VERSION).unwrap_or_else(|_| REGISTRY_LIST_OFFICIAL.to_string());
+      (
+        Resolved::Version(get_latest_remote_version(&registry)?),
+        Source::Latest {
+          registry: redact_url(&registry),
+          offline,
+        },
+      )
+    }
+    Spec::Lts => (
+      Resolved::Version(get_latest_lts_version()?),
+      Source::Lts {
+        source: if offline {
+          dvm_root().join(DVM_CACHE_LTS_PATH).display().to_string()
+        } else {
+          redact_url(&lts_source())
+        },
+        offline,
+      },
+    ),
+    Spec::Canary => (Resolved::Canary, Source::Canary),
+    Spec::System => (Resolved::System, Source::System),
+    Spec::Toolchain(name) => {
+      let toolchain = meta
+        .get_toolchain(name)
+        .ok_or_else(|| anyhow::anyhow!("No toolchain is linked as {}", name))?;
+      (
+        Resolved::Toolchain {
+          name: toolchain.name.clone(),
+          path: toolchain.path.clone(),
+        },
+        Source::Toolchain,
+      )
+    }
+    Spec::Alias(name, target) => {
+      let (resolved, source) = resolve_spec(meta, target)?;
+      (
+        resolved,
+        Source::Alias {
+          name: name.clone(),
+          target: target.to_string(),
+          source: Box::new(source),
+        },
+      )
+    }
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::meta::{Alias, Toolchain};
+
+  fn meta() -> DvmMeta {
+    DvmMeta {
+      alias: vec![
+        Alias {
+          name: "stable".to_string(),
+          required: "1.40".to_string(),
+        },
+        Alias {
+          name: "old".to_string(),
+          required: "lts".to_string(),
+        },
+      ],
+      toolchains: vec![Toolchain {
+        name: "local".to_string(),
+        path: PathBuf::from("/src/deno/target/debug/deno"),
+      }],
+      ..Default::default()
+    }
+  }
+
+  fn range(range: &str) -> Spec {
+    Spec::Range(VersionReq::parse(range).unwrap())
+  }
+
+  #[test]
+  fn parses_keywords_and_exact_versions() {
+    let meta = meta();
+    assert_eq!(parse(&meta, "latest").unwrap(), Spec::Latest);
+    assert_eq!(parse(&meta, "*").unwrap(), Spec::Latest);
+    assert_eq!(parse(&meta, "lts").unwrap(), Spec::Lts);
+    assert_eq!(parse(&meta, "canary").unwrap(), Spec::Canary);
+    assert_eq!(parse(&meta, "system").unwrap(), Spec::System);
+    assert_eq!(
+      parse(&meta, " v1.2.3 \n").unwrap(),
+      Spec::Exact(Version::parse("1.2.3").unwrap())
+    );
+  }
+
+  #[test]
+  fn partial_versions_are_ranges() {
+    let meta = meta();
+    assert_eq!(parse(&meta, "1.40").unwrap(), range("~1.40"));
+    assert_eq!(parse(&meta, "v2").unwrap(), range("^2"));
+    assert_eq!(parse(&meta, ">=1.40, <2").unwrap(), range(">=1.40, <2"));
+    assert_eq!(parse(&meta, "1.40.x").unwrap(), range("1.40.*"));
+    assert!(parse(&meta, "1.").is_err());
+    assert!(parse(&meta, "not-a-version").is_err());
+    assert_eq!(parse_alias_target(&meta, "1.40"), Some(range("~1.40")));
+    assert_eq!(parse_alias_target(&meta, "x y"), None);
+  }
+
+  #[test]
+  fn only_full_ranges_install_every_match() {
+    let meta = meta();
+    assert!(is_range(&meta, "^1.45"));
+    assert!(is_range(&meta, ">=1.40, <2"));
+    assert!(!is_range(&meta, "1.46"));
+    assert!(!is_range(&meta, "2"));
+    assert!(!is_range(&meta, "stable"));
+    assert!(!is_range(&meta, "latest"));
+  }
+
+  #[test]
+  fn parses_aliases_and_toolchains() {
+    let meta = meta();
+    assert_eq!(parse(&meta, "local").unwrap(), Spec::Toolchain("local".to_string()));
+    assert_eq!(
+      parse(&meta, "stable").unwrap(),
+      Spec::Alias("stable".to_string(), Box::new(range("~1.40")))
+    );
+    assert_eq!(
+      parse(&meta, "old").unwrap(),
+      Spec::Alias("old".to_string(), Box::new(Spec::Lts))
+    );
+  }
+
+  #[test]
+  fn explains_aliases_through_their_target() {
+    let resolution = Resolution {
+      input: "stable".to_string(),
+      resolved: Resolved::Version(Version::parse("1.40.5").unwrap()),
+      source: Source::Alias {
+        name: "stable".to_string(),
+        target: "~1.40".to_string(),
+        source: Box::new(Source::Range {
+          range: VersionReq::parse("~1.40").unwrap(),
+          candidates: Candidates::Local { count: 5 },
+        }),
+      },
+    };
+    assert_eq!(
+      resolution.explain(),
+      vec![
+        "stable is an alias of ~1.40",
+        "The highest version matching ~1.40 out of 5 installed or cached versions is v1.40.5"
+      ]
+    );
+  }
+}
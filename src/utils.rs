@@ -1,14 +1,15 @@
-use crate::configrc::rc_get_with_fix;
-use crate::consts::{DENO_EXE, DVM_CACHE_PATH_PREFIX, DVM_CANARY_PATH_PREFIX, DVM_CONFIGRC_KEY_DENO_VERSION};
-use crate::version::VersionArg;
+use crate::consts::{
+  DENO_EXE, DVM_ARCHIVES_PATH_PREFIX, DVM_CACHE_PATH_PREFIX, DVM_CANARY_PATH_PREFIX, DVM_DOWNLOAD_PATH_PREFIX,
+  DVM_REGISTRY_PATH_PREFIX, DVM_STAGING_PATH_PREFIX,
+};
 use anyhow::Result;
 use dirs::home_dir;
 use semver::{Version, VersionReq};
+use sha2::{Digest, Sha256};
 use std::env;
 use std::fs::write;
 use std::io::{stdin, stdout, BufReader, Read, Write};
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::path::{Path, PathBuf};
 use std::time;
 use std::time::{SystemTime, UNIX_EPOCH};
 use tempfile::TempDir;
@@ -39,6 +40,77 @@ pub fn run_with_spinner(
   result
 }
 
+/// a progress bar showing downloaded bytes, rate and eta,
+/// or a byte counting spinner if the size is unknown
+pub fn download_progress_bar(total: Option<u64>) -> indicatif::ProgressBar {
+  match total {
+    Some(total) => {
+      let progress = indicatif::ProgressBar::new(total);
+      progress.set_style(
+        indicatif::ProgressStyle::default_bar()
+          .template("{spinner:.green} {prefix}[{bar:40.cyan/blue}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})")
+          .unwrap()
+          .progress_chars("=> "),
+      );
+      progress
+    }
+    None => {
+      let progress = indicatif::ProgressBar::new_spinner();
+      progress.set_style(
+        indicatif::ProgressStyle::default_spinner()
+          .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏ ")
+          .template("{spinner:.green} {prefix}{bytes} ({bytes_per_sec})")
+          .unwrap(),
+      );
+      progress
+    }
+  }
+}
+
+/// where the messages and the progress bar of a download go, straight to the terminal,
+/// or above the bars of a `MultiProgress` when several downloads run at once
+#[derive(Clone, Default)]
+pub struct Reporter {
+  multi: Option<indicatif::MultiProgress>,
+  /// put in front of the progress bar to tell the downloads apart
+  label: String,
+}
+
+impl Reporter {
+  pub fn multi(multi: &indicatif::MultiProgress, label: String) -> Self {
+    Reporter {
+      multi: Some(multi.clone()),
+      label,
+    }
+  }
+
+  pub fn println(&self, message: impl AsRef<str>) {
+    match &self.multi {
+      // a hidden `MultiProgress`, e.g. not on a terminal, drops what it prints
+      Some(multi) if !multi.is_hidden() => multi.println(message).unwrap_or_default(),
+      _ => println!("{}", message.as_ref()),
+    }
+  }
+
+  pub fn eprintln(&self, message: impl AsRef<str>) {
+    match &self.multi {
+      Some(multi) if !multi.is_hidden() => multi.println(message).unwrap_or_default(),
+      _ => eprintln!("{}", message.as_ref()),
+    }
+  }
+
+  pub fn progress_bar(&self, total: Option<u64>) -> indicatif::ProgressBar {
+    let progress = download_progress_bar(total);
+    match &self.multi {
+      Some(multi) => {
+        progress.set_prefix(format!("{} ", self.label));
+        multi.add(progress)
+      }
+      None => progress,
+    }
+  }
+}
+
 pub fn prompt_request(prompt: &str) -> bool {
   print!("{} (Y/n)", prompt);
 
@@ -71,6 +143,25 @@ pub fn update_stub(verison: &str) {
   }
 }
 
+/// write to a temporary file next to `path` and rename it over `path`,
+/// so readers never see a partially written file.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> std::io::Result<()> {
+  let dir = path.parent().unwrap_or_else(|| Path::new("."));
+  let mut file = tempfile::NamedTempFile::new_in(dir)?;
+  file.write_all(contents.as_ref())?;
+  file.persist(path).map_err(|err| err.error)?;
+  Ok(())
+}
+
+/// like `write_atomic`, with the contents streamed from a reader
+pub fn copy_atomic(path: &Path, mut contents: impl Read) -> std::io::Result<()> {
+  let dir = path.parent().unwrap_or_else(|| Path::new("."));
+  let mut file = tempfile::NamedTempFile::new_in(dir)?;
+  std::io::copy(&mut contents, &mut file)?;
+  file.persist(path).map_err(|err| err.error)?;
+  Ok(())
+}
+
 pub fn is_exact_version(input: &str) -> bool {
   Version::parse(input).is_ok()
 }
@@ -93,15 +184,6 @@ where
     .max_by(|a, b| a.partial_cmp(b).unwrap())
 }
 
-///
-/// Find and load the dvmrc
-/// local -> user -> default
-pub fn load_dvmrc() -> VersionArg {
-  rc_get_with_fix(DVM_CONFIGRC_KEY_DENO_VERSION)
-    .map(|v| VersionArg::from_str(&v).unwrap())
-    .unwrap_or_else(|_| VersionArg::from_str("*").unwrap())
-}
-
 pub fn dvm_root() -> PathBuf {
   env::var_os("DVM_DIR").map(PathBuf::from).unwrap_or_else(|| {
     // Note: on Windows, the $HOME environment variable may be set by users or by
@@ -118,6 +200,26 @@ pub fn dvm_versions() -> PathBuf {
   home
 }
 
+/// installs are unpacked here first and moved into `versions` once complete
+pub fn dvm_staging() -> PathBuf {
+  dvm_root().join(DVM_STAGING_PATH_PREFIX)
+}
+
+/// partially downloaded archives are kept here so they can be resumed
+pub fn dvm_downloads() -> PathBuf {
+  dvm_root().join(DVM_DOWNLOAD_PATH_PREFIX)
+}
+
+/// verified archives are kept here in the registry layout for reinstalls
+pub fn dvm_archives() -> PathBuf {
+  dvm_root().join(DVM_ARCHIVES_PATH_PREFIX)
+}
+
+/// `dvm registry serve` keeps what it fetched from its upstream here in the registry layout
+pub fn dvm_registry_cache() -> PathBuf {
+  dvm_root().join(DVM_REGISTRY_PATH_PREFIX)
+}
+
 pub fn deno_canary_path() -> PathBuf {
   let dvm_dir = dvm_root().join(DVM_CANARY_PATH_PREFIX);
   dvm_dir.join(DENO_EXE)
@@ -145,6 +247,30 @@ pub fn is_http_like_url(url: &str) -> bool {
   url.starts_with("http://") || url.starts_with("https://")
 }
 
+/// the lowercase hex sha256 digest of the data
+pub fn sha256_hex(data: &[u8]) -> String {
+  Sha256::digest(data)
+    .iter()
+    .map(|byte| format!("{:02x}", byte))
+    .collect()
+}
+
+/// the lowercase hex sha256 digest of everything the reader yields, e.g. a file too big to read into memory
+pub fn sha256_reader(mut reader: impl Read) -> std::io::Result<String> {
+  let mut hasher = Sha256::new();
+  std::io::copy(&mut reader, &mut hasher)?;
+  Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// binary registries are joined with the path of a release
+pub fn with_trailing_slash(registry: &str) -> String {
+  if registry.ends_with('/') || registry.ends_with(std::path::MAIN_SEPARATOR) {
+    registry.to_string()
+  } else {
+    format!("{}/", registry)
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -152,7 +278,7 @@ mod tests {
 
   #[test]
   fn test_best_version() {
-    let versions = vec![
+    let versions = [
       "0.8.5",
       "0.8.0",
       "0.9.0",
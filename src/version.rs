@@ -1,25 +1,27 @@
 // Copyright 2022 justjavac. All rights reserved. MIT license.
-use crate::configrc::rc_get_with_fix;
+use crate::archives;
+use crate::configrc::{rc_get, rc_get_with_fix};
 use crate::consts::{
-  DVM_CACHE_PATH_PREFIX, DVM_CACHE_REMOTE_PATH, DVM_CONFIGRC_KEY_REGISTRY_VERSION, DVM_VERSION_LTS,
-  REGISTRY_LATEST_CANARY_PATH, REGISTRY_LATEST_RELEASE_PATH,
+  ARCHIVE_NAME, DENO_EXE, DVM_CACHE_LTS_PATH, DVM_CACHE_PATH_PREFIX, DVM_CACHE_REMOTE_PATH,
+  DVM_CONFIGRC_KEY_REGISTRY_LTS, DVM_CONFIGRC_KEY_REGISTRY_VERSION, REGISTRY_LATEST_CANARY_PATH,
+  REGISTRY_LATEST_RELEASE_PATH, REGISTRY_LTS_OFFICIAL,
 };
-use crate::utils::{dvm_root, is_exact_version, is_semver, run_with_spinner};
+use crate::http;
+use crate::meta::DvmMeta;
+use crate::oci;
+use crate::utils::{dvm_root, is_semver, run_with_spinner, write_atomic};
 use anyhow::Result;
 use colored::Colorize;
 use json_minimal::Json;
 use semver::{Version, VersionReq};
 use serde::{Deserialize, Serialize};
-use std::fmt::Formatter;
 use std::fs::read_dir;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::str::FromStr;
 use std::string::String;
 
 pub const DVM: &str = env!("CARGO_PKG_VERSION");
-const DENO_RELEASES_LTS_SEARCH: &str = "https://github.com/denoland/deno/releases?q=LTS";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Cached {
@@ -27,40 +29,6 @@ pub struct Cached {
   time: String,
 }
 
-#[derive(Debug, Eq, PartialEq, Clone)]
-pub enum VersionArg {
-  Exact(Version),
-  Range(VersionReq),
-  Lts,
-}
-
-impl std::fmt::Display for VersionArg {
-  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-    match self {
-      VersionArg::Exact(version) => f.write_str(version.to_string().as_str()),
-      VersionArg::Range(version) => f.write_str(version.to_string().as_str()),
-      VersionArg::Lts => f.write_str(DVM_VERSION_LTS),
-    }
-  }
-}
-
-impl FromStr for VersionArg {
-  type Err = ();
-
-  fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-    let s = s.trim();
-    if s == DVM_VERSION_LTS {
-      Ok(VersionArg::Lts)
-    } else if is_exact_version(s) {
-      Version::parse(s).map(VersionArg::Exact).map_err(|_| ())
-    } else {
-      VersionReq::parse(s)
-        .map(VersionArg::Range)
-        .or_else(|_| VersionReq::parse("*").map(VersionArg::Range).map_err(|_| ()))
-    }
-  }
-}
-
 pub fn current_version() -> Option<String> {
   match Command::new("deno").arg("-V").stderr(Stdio::inherit()).output() {
     Ok(output) => {
@@ -74,6 +42,7 @@ pub fn current_version() -> Option<String> {
   }
 }
 
+/// installed versions, followed by the names of linked toolchains
 pub fn local_versions() -> Vec<String> {
   let mut v: Vec<String> = Vec::new();
 
@@ -82,7 +51,8 @@ pub fn local_versions() -> Vec<String> {
       if let Ok(file_type) = entry.file_type() {
         if file_type.is_dir() {
           let file_name = entry.file_name().into_string().unwrap();
-          if is_semver(&file_name) {
+          // a version directory without an executable is not installed
+          if is_semver(&file_name) && entry.path().join(DENO_EXE).exists() {
             v.push(file_name);
           }
         }
@@ -90,6 +60,7 @@ pub fn local_versions() -> Vec<String> {
     }
   }
 
+  v.extend(DvmMeta::new().toolchains.into_iter().map(|it| it.name));
   v
 }
 
@@ -106,24 +77,31 @@ pub fn cache_remote_versions() -> Result<()> {
       let cached_remote_versions_location = cached_remote_versions_location();
 
       let remote_versions_url = rc_get_with_fix(DVM_CONFIGRC_KEY_REGISTRY_VERSION)?;
-      let remote_versions = tinyget::get(remote_versions_url).send()?.as_str()?.to_owned();
-      std::fs::write(cached_remote_versions_location, remote_versions).map_err(|e| anyhow::anyhow!(e))
+      let remote_versions = fetch_versions_json(&remote_versions_url)?;
+      write_atomic(&cached_remote_versions_location, remote_versions).map_err(|e| anyhow::anyhow!(e))
     },
   )
 }
 
 /// use cached remote versions if exists, otherwise ask user to fetch remote versions
 pub fn remote_versions() -> Result<Vec<String>> {
+  if !is_versions_cache_exists() && http::is_offline() {
+    anyhow::bail!("The remote version list is not cached, run `dvm update` while online or leave offline mode");
+  }
   if !is_versions_cache_exists() {
-    println!("It seems that you have not updated the remote version cache, please run `dvm update` first.");
-    print!("Do you want to update the remote version cache now? [Y/n]");
-    std::io::stdout().lock().flush().unwrap();
+    // the prompt goes to stderr so that machine readable output on stdout stays clean
+    if !std::io::stdin().is_terminal() {
+      anyhow::bail!("The remote version list is not cached, run `dvm update` first");
+    }
+    eprintln!("It seems that you have not updated the remote version cache, please run `dvm update` first.");
+    eprint!("Do you want to update the remote version cache now? [Y/n]");
+    std::io::stderr().lock().flush()?;
     let mut input = String::new();
     std::io::stdin().read_line(&mut input)?;
     if input.trim().to_lowercase() == "y" || input.trim().is_empty() {
       cache_remote_versions()?;
     } else {
-      println!("Please run `dvm update` to update the remote version cache.");
+      eprintln!("Please run `dvm update` to update the remote version cache.");
       std::process::exit(1);
     }
   }
@@ -160,64 +138,105 @@ pub fn remote_versions() -> Result<Vec<String>> {
   Ok(result)
 }
 
+/// the versions a range is resolved against,
+/// only the installed and cached versions in offline mode since nothing else can be used
+pub fn candidate_versions() -> Result<Vec<String>> {
+  if http::is_offline() {
+    let mut versions = local_versions();
+    for archive in archives::list().into_iter().filter(|it| it.name == ARCHIVE_NAME) {
+      let version = archive.version.to_string();
+      if !versions.contains(&version) {
+        versions.push(version);
+      }
+    }
+    Ok(versions)
+  } else {
+    remote_versions()
+  }
+}
+
 pub fn is_versions_cache_exists() -> bool {
   let remote_versions_location = cached_remote_versions_location();
   remote_versions_location.exists()
 }
 
 pub fn get_latest_version(registry: &str) -> Result<Version> {
-  let response = tinyget::get(format!("{}{}", registry, REGISTRY_LATEST_RELEASE_PATH)).send()?;
-
-  let body = response.as_str()?;
+  let body = http::get_string(&http::join(registry, REGISTRY_LATEST_RELEASE_PATH))?;
   let v = body.trim().replace('v', "");
   Ok(Version::parse(&v).unwrap())
 }
 
+/// the latest stable release, or the latest installed stable version in offline mode
 pub fn get_latest_remote_version(registry: &str) -> Result<Version> {
-  let response = tinyget::get(registry).send()?;
-  if response.status_code >= 400 {
-    anyhow::bail!("Failed to fetch Deno versions: {}", response.status_code);
+  if http::is_offline() {
+    return local_versions()
+      .iter()
+      .filter_map(|it| Version::parse(it).ok())
+      .filter(|it| it.pre.is_empty())
+      .max()
+      .ok_or_else(|| anyhow::anyhow!("No stable version is installed to use as the latest in offline mode"));
   }
-  latest_version_from_versions_json(response.as_str()?)
+  latest_version_from_versions_json(&fetch_versions_json(registry)?)
+}
+
+/// the `versions.json` of a version registry, built from the tags of an OCI repository
+pub fn fetch_versions_json(registry: &str) -> Result<String> {
+  if oci::is_oci(registry) {
+    return oci::versions_json(registry);
+  }
+  http::get_string(registry)
+}
+
+/// where the latest LTS release is looked up, `registry_lts` in the `.dvmrc` or the Deno releases
+pub fn lts_source() -> String {
+  rc_get(DVM_CONFIGRC_KEY_REGISTRY_LTS)
+    .ok()
+    .filter(|it| !it.is_empty())
+    .unwrap_or_else(|| REGISTRY_LTS_OFFICIAL.to_string())
 }
 
+/// the latest LTS release, remembered for offline mode
 pub fn get_latest_lts_version() -> Result<Version> {
-  let response = tinyget::get(DENO_RELEASES_LTS_SEARCH)
-    .with_header("User-Agent", "dvm")
-    .send()?;
-  if response.status_code >= 400 {
-    anyhow::bail!("Failed to fetch Deno LTS releases: {}", response.status_code);
+  let cached_lts_location = dvm_root().join(DVM_CACHE_LTS_PATH);
+  if http::is_offline() {
+    let cached = std::fs::read_to_string(&cached_lts_location).map_err(|_| {
+      anyhow::anyhow!("The latest LTS version is not known offline, resolve `lts` once while online first")
+    })?;
+    return Version::parse(cached.trim()).map_err(Into::into);
+  }
+
+  let response = http::get(&lts_source())?;
+  if response.status() >= 400 {
+    anyhow::bail!("Failed to fetch Deno LTS releases: {}", response.status());
   }
-  latest_lts_version_from_releases_html(response.as_str()?)
+  let version = latest_lts_version(&response.into_string()?)?;
+  // only a cache, failing to write it is not an error
+  let _ = write_atomic(&cached_lts_location, version.to_string());
+  Ok(version)
 }
 
 pub fn get_latest_canary(registry: &str) -> Result<String> {
-  let response = tinyget::get(format!("{}{}", registry, REGISTRY_LATEST_CANARY_PATH)).send()?;
-
-  let body = response.as_str()?;
+  if oci::is_oci(registry) {
+    anyhow::bail!("Canary builds are not published to OCI registries");
+  }
+  let body = http::get_string(&http::join(registry, REGISTRY_LATEST_CANARY_PATH))?;
   let v = body.trim().replace('v', "");
   Ok(v)
 }
 
-pub fn version_req_parse(version: &str) -> VersionReq {
-  VersionReq::parse(version).unwrap_or_else(|_| panic!("version is invalid: {}", version))
-}
-
-pub fn find_max_matching_version<'a, I>(version_req_str: &str, iterable: I) -> Result<Option<Version>>
+pub fn find_max_matching_version<'a, I>(version_req: &VersionReq, iterable: I) -> Option<Version>
 where
   I: IntoIterator<Item = &'a str>,
 {
-  let version_req = version_req_parse(version_req_str);
-  Ok(
-    iterable
-      .into_iter()
-      .filter_map(|s| Version::parse(s).ok())
-      .filter(|s| version_req.matches(s))
-      .max(),
-  )
+  iterable
+    .into_iter()
+    .filter_map(|s| Version::parse(s).ok())
+    .filter(|s| version_req.matches(s))
+    .max()
 }
 
-fn latest_version_from_versions_json(content: &str) -> Result<Version> {
+/// the highest stable version in a `versions.json`
+pub fn latest_version_from_versions_json(content: &str) -> Result<Version> {
   let versions = cli_versions_from_versions_json(content)?;
   versions
     .iter()
@@ -242,6 +261,14 @@ fn cli_versions_from_versions_json(content: &str) -> Result<Vec<String>> {
   )
 }
 
+/// an LTS source is either a file with just the version, like `release-latest.txt`, or the Deno releases page
+fn latest_lts_version(content: &str) -> Result<Version> {
+  match Version::parse(content.trim().trim_start_matches('v')) {
+    Ok(version) => Ok(version),
+    Err(_) => latest_lts_version_from_releases_html(content),
+  }
+}
+
 fn latest_lts_version_from_releases_html(content: &str) -> Result<Version> {
   content
     .match_indices("/denoland/deno/releases/tag/v")
@@ -289,11 +316,11 @@ mod tests {
   }
 
   #[test]
-  fn version_arg_trims_input() {
+  fn lts_source_can_be_a_version_file() {
     assert_eq!(
-      VersionArg::from_str(" 1.2.3 \n").unwrap(),
-      VersionArg::Exact(Version::parse("1.2.3").unwrap())
+      latest_lts_version("v2.1.4\n").unwrap(),
+      Version::parse("2.1.4").unwrap()
     );
-    assert_eq!(VersionArg::from_str(" lts \n").unwrap(), VersionArg::Lts);
+    assert!(latest_lts_version("<html></html>").is_err());
   }
 }